@@ -1,3 +1,4 @@
+use crate::config::ReadPreference;
 use clap::{Parser, ValueEnum};
 use std::path::PathBuf;
 
@@ -14,20 +15,25 @@ pub enum Subcommand {
         name = "dump",
         about = "source DB로부터 target DB로 mongodump & restore를 실행합니다"
     )]
-    Dump(DumpCommand),
+    Dump(Box<DumpCommand>),
+    #[command(
+        name = "restore",
+        about = "sprt dump --to-file로 저장한 archive를 target DB로 복원합니다\nsprt restore -s online --from-file path/to/online.archive.gz"
+    )]
+    Restore(RestoreCommand),
     #[command(
         name = "resize",
-        about = "이미지 파일 해상도 변경\nsprt resize -i path/to/input_dir [-f file_name] -o path/to/output_dir -w 1920 -h 1080"
+        about = "이미지 파일 해상도 변경\nsprt resize -i path/to/input_dir [-f file_name] -o path/to/output_dir -w 1920 [-h 1080] [-m fit|fill|cover|crop] [-g center] [--filter auto]\nsprt resize -i path/to/input_dir -o path/to/output_dir --widths 320,640,1280 [--manifest srcset.json] [-r]\nsprt resize -i path/to/input_dir -o path/to/output_dir -w 320 --densities 1,2,3 [--manifest srcset.json]"
     )]
     Resize(ResizeCommand),
     #[command(
         name = "compress",
-        about = "이미지 손실/무손실 압축(손실 압축시 tiny png 사이트 방식)\nsprt compress -i path/to/input_dir [-f file_name] -o path/to/output_dir -d"
+        about = "이미지 손실/무손실 압축(손실 압축시 tiny png 사이트 방식)\nsprt compress -i path/to/input_dir [-f file_name] -o path/to/output_dir -d [-r]"
     )]
     Compress(CompressCommand),
     #[command(
         name = "webpify",
-        about = "png/jpeg를 webp로 변환\nsprt webpify -i path/to/input_dir -o path/to/output_dir"
+        about = "png/jpeg를 webp로 변환\nsprt webpify -i path/to/input_dir -o path/to/output_dir [-r]"
     )]
     Webpify(WebpifyCommand),
     #[command(
@@ -37,28 +43,472 @@ pub enum Subcommand {
     Cred(CredCommand),
 }
 
+/// `sprt dump -s online ...`처럼 바로 dump를 실행하거나, `sprt dump verify ...`처럼 하위 명령을 실행합니다.
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 pub struct DumpCommand {
-    #[arg(short, long)]
+    #[command(subcommand)]
+    pub action: Option<DumpAction>,
+    #[command(flatten)]
+    pub args: Option<DumpArgs>,
+}
+
+#[derive(Parser)]
+pub enum DumpAction {
+    #[command(
+        name = "verify",
+        about = "source와 target의 document 수, index, validator, collection 옵션을 비교합니다\nsprt dump verify -s online [-c rounds users]"
+    )]
+    Verify(VerifyCommand),
+    #[command(
+        name = "diff",
+        about = "source와 target에서 document를 sampling해 collection/field/type 차이를 보여줍니다\nsprt dump diff -s online [--sample 100] [--format json]"
+    )]
+    Diff(DiffCommand),
+    #[command(
+        name = "export",
+        about = "collection을 test fixture 파일(ndjson/json/ejson)로 저장합니다\nsprt dump export -s online -c rounds users --sample 100 -o path/to/fixtures"
+    )]
+    Export(ExportCommand),
+    #[command(
+        name = "import",
+        about = "sprt dump export로 만든 fixture 파일을 target DB로 넣습니다\nsprt dump import -s online -i path/to/fixtures"
+    )]
+    Import(ImportCommand),
+    #[command(
+        name = "history",
+        about = "지금까지 실행한 sprt dump 기록을 보여줍니다\nsprt dump history [-s online] [-n 20] [--failed]"
+    )]
+    History(HistoryCommand),
+    #[command(
+        name = "schedule",
+        about = "config의 schedules에 적힌 cron 표현식에 맞춰 sprt dump를 계속 실행합니다\nsprt dump schedule [-s online] [--dry-run]"
+    )]
+    Schedule(ScheduleCommand),
+    #[command(
+        name = "sql",
+        about = "config의 sql_dump service를 pg_dump/mysqldump로 target DB에 복사합니다\nsprt dump sql -s billing [--tables users orders | -f order] [--dry-run]"
+    )]
+    Sql(SqlDumpCommand),
+}
+
+#[derive(Parser)]
+pub struct DumpArgs {
+    // flatten한 CollectionSelection이 있으면 clap이 DumpArgs group을 비워 두므로 직접 넣습니다.
+    #[arg(short, long, group = "DumpArgs")]
     pub service: String,
+    #[arg(
+        long = "db",
+        num_args(1..),
+        help = "복사할 database(config의 db_name/databases). 없으면 service의 모든 database"
+    )]
+    pub databases: Vec<String>,
+    #[command(flatten)]
+    pub selection: CollectionSelection,
+    #[arg(long, help = "실행하지 않고 mongodump/mongorestore 계획만 출력합니다")]
+    pub dry_run: bool,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = DumpEngine::Tools,
+        help = "tools: mongodump/mongorestore 실행, native: 드라이버로 직접 복사(database tools 불필요)"
+    )]
+    pub engine: DumpEngine,
+    #[arg(
+        long,
+        help = "실패한 collection 재시도 횟수(config의 retries를 덮어씁니다)"
+    )]
+    pub retries: Option<u32>,
+    #[arg(
+        short,
+        long,
+        help = "동시에 실행할 collection pipeline 수(config의 jobs를 덮어씁니다)"
+    )]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        conflicts_with = "sample",
+        help = "collection마다 앞에서부터 N개만 복사합니다(native engine)"
+    )]
+    pub limit: Option<u64>,
+    #[arg(long, help = "collection마다 무작위로 N개만 복사합니다(native engine)")]
+    pub sample: Option<u64>,
+    #[arg(
+        long,
+        help = "target DB 대신 mongodump archive(gzip) 파일로 저장합니다"
+    )]
+    pub to_file: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        help = "복원할 db 이름 template({db}, {part}, {user}, {date}). 기본값은 config의 target_db 또는 {db}_{part}"
+    )]
+    pub target_db: Option<String>,
+    #[arg(short, long, help = "drop 확인 질문 없이 바로 실행합니다")]
+    pub yes: bool,
+    #[arg(
+        long,
+        help = "target collection을 drop하지 않고 없는 document만 더합니다"
+    )]
+    pub no_drop: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "source를 읽을 read preference(config의 read_preference를 덮어씁니다)"
+    )]
+    pub read_preference: Option<ReadPreference>,
+    #[arg(
+        long,
+        help = "source에서 초당 읽을 document 수(native engine, config의 throttle.docs_per_sec를 덮어씁니다)"
+    )]
+    pub docs_per_sec: Option<u64>,
+    #[arg(
+        long,
+        help = "source에서 초당 읽을 MB(config의 throttle.mb_per_sec를 덮어씁니다)"
+    )]
+    pub mb_per_sec: Option<f64>,
+    #[arg(
+        long,
+        help = "collection 사이에 쉬는 시간(ms, config의 throttle.pause_ms를 덮어씁니다)"
+    )]
+    pub pause_ms: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with_all = ["limit", "sample", "to_file"],
+        help = "지난 동기화 이후 새로 생기거나 바뀐 document만 target에 덮어씁니다(native engine, drop하지 않음)"
+    )]
+    pub incremental: bool,
+    #[arg(
+        long,
+        help = "source의 collection 중 includes/excludes에 따라 복사될 collection 목록만 보여줍니다"
+    )]
+    pub list: bool,
+    #[arg(
+        long,
+        conflicts_with = "to_file",
+        help = "복사가 끝난 뒤 source와 target의 document 수, index, collection 옵션을 비교합니다"
+    )]
+    pub verify: bool,
+}
+
+/// 복사할 collection을 고르는 옵션. `sprt dump`와 `sprt dump export`가 함께 씁니다.
+#[derive(Parser)]
+pub struct CollectionSelection {
     #[arg(short, long, num_args(0..))]
     pub collections: Option<Vec<String>>,
     #[arg(short, long)]
     pub family: Option<String>,
+    #[arg(
+        short,
+        long = "query",
+        value_parser = parse_collection_query,
+        help = "collection별 filter(extended JSON). 예: -q 'rounds={\"is_active\":true}'"
+    )]
+    pub queries: Vec<(String, String)>,
+    #[arg(
+        long,
+        requires = "family",
+        value_parser = parse_query,
+        help = "family의 첫 collection에 적용할 filter(extended JSON). links로 연결된 collection은 참조되는 document만 복사합니다"
+    )]
+    pub filter: Option<String>,
 }
 
 #[derive(Parser)]
-pub struct ResizeCommand {
+pub struct VerifyCommand {
     #[arg(short, long)]
-    pub input_dir: PathBuf,
+    pub service: String,
+    #[arg(
+        long = "db",
+        num_args(1..),
+        help = "비교할 database. 없으면 service의 모든 database"
+    )]
+    pub databases: Vec<String>,
+    #[arg(
+        short,
+        long,
+        num_args(1..),
+        help = "비교할 collection. 없으면 includes/excludes에 따라 복사되는 collection 전체"
+    )]
+    pub collections: Option<Vec<String>>,
+    #[arg(
+        short,
+        long,
+        help = "비교할 db 이름 template({db}, {part}, {user}, {date})"
+    )]
+    pub target_db: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct DiffCommand {
+    #[arg(short, long)]
+    pub service: String,
+    #[arg(
+        long = "db",
+        num_args(1..),
+        help = "비교할 database. 없으면 service의 모든 database"
+    )]
+    pub databases: Vec<String>,
+    #[arg(
+        short,
+        long,
+        num_args(1..),
+        help = "비교할 collection. 없으면 includes/excludes에 따라 복사되는 collection 전체"
+    )]
+    pub collections: Option<Vec<String>>,
+    #[arg(
+        short,
+        long,
+        help = "비교할 db 이름 template({db}, {part}, {user}, {date})"
+    )]
+    pub target_db: Option<String>,
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "collection마다 양쪽에서 무작위로 읽을 document 수"
+    )]
+    pub sample: u64,
+    #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+    pub format: DiffFormat,
+}
+
+#[derive(Parser)]
+pub struct ExportCommand {
+    #[arg(short, long)]
+    pub service: String,
+    #[arg(
+        long = "db",
+        num_args(1..),
+        help = "내보낼 database. 여럿이면 database마다 하위 directory를 만듭니다"
+    )]
+    pub databases: Vec<String>,
+    #[command(flatten)]
+    pub selection: CollectionSelection,
     #[arg(short, long)]
     pub output_dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = FixtureFormat::Ndjson)]
+    pub format: FixtureFormat,
+    #[arg(
+        long,
+        conflicts_with = "sample",
+        help = "collection마다 앞에서부터 N개만 내보냅니다"
+    )]
+    pub limit: Option<u64>,
+    #[arg(long, help = "collection마다 무작위로 N개만 내보냅니다")]
+    pub sample: Option<u64>,
+}
+
+#[derive(Parser)]
+pub struct ImportCommand {
     #[arg(short, long)]
-    pub width: u32,
+    pub service: String,
+    #[arg(
+        long = "db",
+        help = "fixture를 넣을 database. service에 database가 여럿이면 필요합니다"
+    )]
+    pub database: Option<String>,
+    #[arg(short, long)]
+    pub input_dir: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "넣을 db 이름 template({db}, {part}, {user}, {date})"
+    )]
+    pub target_db: Option<String>,
+    #[arg(long, help = "실행하지 않고 넣을 fixture 목록만 출력합니다")]
+    pub dry_run: bool,
+    #[arg(short, long, help = "drop 확인 질문 없이 바로 실행합니다")]
+    pub yes: bool,
+    #[arg(
+        long,
+        help = "target collection을 drop하지 않고 없는 document만 더합니다"
+    )]
+    pub no_drop: bool,
+}
+
+#[derive(Parser)]
+pub struct HistoryCommand {
+    #[arg(short, long, help = "이 service의 기록만 보여줍니다")]
+    pub service: Option<String>,
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = 20,
+        help = "최근 N개만 보여줍니다"
+    )]
+    pub limit: usize,
+    #[arg(long, help = "실패한 collection이 있는 기록만 보여줍니다")]
+    pub failed: bool,
+}
+
+#[derive(Parser)]
+pub struct ScheduleCommand {
+    #[arg(
+        short,
+        long = "service",
+        num_args(1..),
+        help = "실행할 service. 없으면 schedules가 있는 모든 service"
+    )]
+    pub services: Vec<String>,
+    #[arg(long, help = "실행하지 않고 schedule과 다음 실행 시각만 출력합니다")]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct SqlDumpCommand {
     #[arg(short, long)]
-    pub height: u32,
+    pub service: String,
+    #[arg(
+        long,
+        num_args(1..),
+        conflicts_with = "family",
+        help = "복사할 table. 없으면 config의 includes/excludes에 따라 복사합니다"
+    )]
+    pub tables: Option<Vec<String>>,
+    #[arg(short, long, help = "config의 family에 적힌 table만 복사합니다")]
+    pub family: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "복원할 database 이름 template({db}, {part}, {user}, {date}). 기본값은 config의 target_db 또는 {db}_{part}"
+    )]
+    pub target_db: Option<String>,
+    #[arg(long, help = "실행하지 않고 dump/restore 명령만 출력합니다")]
+    pub dry_run: bool,
+    #[arg(short, long, help = "drop 확인 질문 없이 바로 실행합니다")]
+    pub yes: bool,
+    #[arg(
+        long,
+        help = "target table을 지우지 않고 key가 겹치지 않는 row만 더합니다"
+    )]
+    pub no_drop: bool,
+}
+
+#[derive(Parser)]
+pub struct RestoreCommand {
+    #[arg(short, long)]
+    pub service: String,
+    #[arg(
+        long = "db",
+        help = "archive를 만든 database. service에 database가 여럿이면 필요합니다"
+    )]
+    pub database: Option<String>,
+    #[arg(long)]
+    pub from_file: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "복원할 db 이름 template({db}, {part}, {user}, {date})"
+    )]
+    pub target_db: Option<String>,
+    #[arg(long, help = "실행하지 않고 mongorestore 계획만 출력합니다")]
+    pub dry_run: bool,
+    #[arg(short, long, help = "drop 확인 질문 없이 바로 실행합니다")]
+    pub yes: bool,
+    #[arg(
+        long,
+        help = "target collection을 drop하지 않고 없는 document만 더합니다"
+    )]
+    pub no_drop: bool,
+}
+
+fn parse_collection_query(arg: &str) -> Result<(String, String), String> {
+    let (collection, query) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("COLLECTION=QUERY 형식이 아닙니다: {}", arg))?;
+
+    serde_json::from_str::<serde_json::Value>(query)
+        .map_err(|e| format!("{}의 query가 올바른 JSON이 아닙니다: {}", collection, e))?;
+
+    Ok((collection.to_string(), query.to_string()))
+}
+
+fn parse_query(arg: &str) -> Result<String, String> {
+    serde_json::from_str::<serde_json::Value>(arg)
+        .map_err(|e| format!("query가 올바른 JSON이 아닙니다: {}", e))?;
+
+    Ok(arg.to_string())
+}
+
+#[derive(Parser)]
+pub struct ResizeCommand {
+    #[arg(short, long)]
+    pub input_dir: PathBuf,
+    #[arg(short, long)]
+    pub output_dir: PathBuf,
+    #[arg(
+        short,
+        long,
+        required_unless_present_any = ["height", "widths"],
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "결과 너비. 높이만 주면 비율에 맞춰 정합니다"
+    )]
+    pub width: Option<u32>,
+    #[arg(
+        short,
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "결과 높이. 너비만 주면 비율에 맞춰 정합니다"
+    )]
+    pub height: Option<u32>,
     #[arg(short, long)]
     pub file_name: Option<String>,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = ResizeMode::Fill,
+        help = "너비와 높이를 모두 줬을 때 맞추는 방식"
+    )]
+    pub mode: ResizeMode,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = Gravity::Center,
+        help = "cover/crop에서 남길 부분"
+    )]
+    pub gravity: Gravity,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ResizeFilter::Lanczos3,
+        help = "resampling filter. 큰 batch는 auto가 축소 비율을 보고 빠른 쪽을 고릅니다"
+    )]
+    pub filter: ResizeFilter,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with_all = ["width", "height"],
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "여러 너비로 한 번에 만듭니다. 예: --widths 320,640,1280 (원본보다 큰 너비는 건너뜁니다)"
+    )]
+    pub widths: Option<Vec<u32>>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with = "widths",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "-w/-h를 1x로 보고 배수별로 만듭니다. 예: -w 320 --densities 1,2,3 (원본보다 커지는 배수는 건너뜁니다)"
+    )]
+    pub densities: Option<Vec<u32>>,
+    #[arg(
+        long = "name",
+        help = "결과 파일 이름 template({stem}, {ext}, {w}, {h}, {d}). 기본값은 --widths면 {stem}@{w}w.{ext}, --densities면 {stem}@{d}x.{ext}, 없으면 원본 이름"
+    )]
+    pub name_template: Option<String>,
+    #[arg(long, help = "원본별 srcset과 결과 파일 목록을 JSON으로 저장합니다")]
+    pub manifest: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        conflicts_with = "file_name",
+        help = "하위 디렉토리까지 처리하고, --output-dir 아래에 같은 디렉토리 구조로 저장합니다"
+    )]
+    pub recursive: bool,
+    #[arg(short, long, help = "동시에 처리할 파일 수. 기본값은 CPU core 수")]
+    pub jobs: Option<usize>,
 }
 
 #[derive(Parser)]
@@ -77,6 +527,15 @@ pub struct CompressCommand {
     pub speed: i64,
     #[arg(short, long, default_value_t = 65, value_parser = 1..=100)]
     pub quality: i64,
+    #[arg(
+        short,
+        long,
+        conflicts_with = "file_name",
+        help = "하위 디렉토리까지 처리하고, --output-dir 아래에 같은 디렉토리 구조로 저장합니다"
+    )]
+    pub recursive: bool,
+    #[arg(short, long, help = "동시에 처리할 파일 수. 기본값은 CPU core 수")]
+    pub jobs: Option<usize>,
 }
 #[derive(Parser)]
 pub struct WebpifyCommand {
@@ -84,6 +543,14 @@ pub struct WebpifyCommand {
     pub input_dir: PathBuf,
     #[arg(short, long)]
     pub output_dir: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "하위 디렉토리까지 처리하고, --output-dir 아래에 같은 디렉토리 구조로 저장합니다. resize/compress와 같이 기본값은 바로 아래 파일만 처리합니다"
+    )]
+    pub recursive: bool,
+    #[arg(short, long, help = "동시에 처리할 파일 수. 기본값은 CPU core 수")]
+    pub jobs: Option<usize>,
 }
 
 #[derive(Parser)]
@@ -108,6 +575,68 @@ pub struct CredCommand {
     pub region: Option<String>,
 }
 
+#[derive(ValueEnum, Clone)]
+pub enum DumpEngine {
+    Tools,
+    Native,
+}
+
+/// ndjson/json은 relaxed, ejson은 canonical extended JSON으로 type을 그대로 보존합니다.
+#[derive(ValueEnum, Clone)]
+pub enum FixtureFormat {
+    Ndjson,
+    Json,
+    Ejson,
+}
+
+#[derive(ValueEnum, Clone)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+/// CSS `object-fit`과 같은 의미입니다.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ResizeMode {
+    /// 비율을 지키며 너비×높이 안에 들어가도록 줄이거나 늘립니다.
+    Fit,
+    /// 비율을 무시하고 정확히 너비×높이로 늘립니다.
+    Fill,
+    /// 비율을 지키며 너비×높이를 덮도록 맞춘 뒤 넘치는 부분을 잘라냅니다.
+    Cover,
+    /// 크기를 바꾸지 않고 너비×높이만큼 잘라냅니다.
+    Crop,
+}
+
+/// nearest부터 lanczos3까지는 뒤로 갈수록 느리지만 결과가 선명합니다.
+/// super-sampling은 크게 줄일 때 빠르게 계단 현상을 줄이는 용도입니다.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ResizeFilter {
+    /// 축소 비율에 따라 고릅니다. 크게 줄일 때는 super-sampling, 키울 때는 catmull-rom, 나머지는 lanczos3.
+    Auto,
+    /// 가장 가까운 pixel을 그대로 씁니다. 가장 빠르지만 계단 현상이 생깁니다.
+    Nearest,
+    Bilinear,
+    CatmullRom,
+    Mitchell,
+    Lanczos3,
+    /// nearest로 결과의 몇 배 크기까지 먼저 줄인 뒤 lanczos3로 마무리합니다. 크게 줄일 때 빠릅니다.
+    SuperSampling,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Gravity {
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
 #[derive(ValueEnum, Clone)]
 pub enum CredMode {
     Develop,
@@ -1,5 +1,5 @@
 use clap::Parser;
-use cli::{Cli, Subcommand};
+use cli::{Cli, DumpAction, Subcommand};
 use config::Config;
 
 mod cli;
@@ -13,7 +13,34 @@ async fn main() {
     let config = Config::new().expect("failed to get config");
 
     match opts.subcommand {
-        Subcommand::Dump(dump_opts) => sub::dump::run_dump(dump_opts, config.mongo_dump),
+        Subcommand::Dump(dump_command) => match (dump_command.action, dump_command.args) {
+            (Some(DumpAction::Verify(verify_opts)), _) => {
+                sub::dump::run_verify(verify_opts, config.mongo_dump).await
+            }
+            (Some(DumpAction::Diff(diff_opts)), _) => {
+                sub::dump::run_diff(diff_opts, config.mongo_dump).await
+            }
+            (Some(DumpAction::Export(export_opts)), _) => {
+                sub::dump::run_export(export_opts, config.mongo_dump).await
+            }
+            (Some(DumpAction::Import(import_opts)), _) => {
+                sub::dump::run_import(import_opts, config.mongo_dump, config.target_guard).await
+            }
+            (Some(DumpAction::History(history_opts)), _) => sub::dump::run_history(history_opts),
+            (Some(DumpAction::Schedule(schedule_opts)), _) => {
+                sub::dump::run_schedule(schedule_opts, config.mongo_dump).await
+            }
+            (Some(DumpAction::Sql(sql_opts)), _) => {
+                sub::dump::run_sql_dump(sql_opts, config.sql_dump, config.target_guard)
+            }
+            (None, Some(dump_opts)) => {
+                sub::dump::run_dump(dump_opts, config.mongo_dump, config.target_guard).await
+            }
+            (None, None) => unreachable!("clap requires arguments for sprt dump"),
+        },
+        Subcommand::Restore(restore_opts) => {
+            sub::dump::run_restore(restore_opts, config.mongo_dump, config.target_guard)
+        }
         Subcommand::Resize(resize_opts) => sub::resize::run_resize(resize_opts),
         Subcommand::Compress(compress_opts) => sub::compress::run_compress(compress_opts),
         Subcommand::Webpify(webpify_opts) => sub::webpify::run_webpify(webpify_opts),
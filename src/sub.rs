@@ -1,5 +1,6 @@
 pub mod compress;
 pub mod credential;
 pub mod dump;
+pub mod image_files;
 pub mod resize;
 pub mod webpify;
@@ -34,6 +34,7 @@ pub async fn run_credential(cred_opts: CredCommand) {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open(session_path);
 
     let mut session_key = String::new();
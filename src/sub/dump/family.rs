@@ -0,0 +1,181 @@
+use super::native;
+use crate::{
+    config::{CollectionLink, FamilyMember},
+    exit_with_error,
+};
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Database,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// 다른 family 참조까지 모두 펼친 family
+#[derive(Default)]
+pub struct ResolvedFamily {
+    /// config에 적힌 순서. 첫 collection이 `--filter`를 적용할 기준 collection입니다.
+    pub collections: Vec<String>,
+    pub queries: HashMap<String, String>,
+}
+
+pub fn expand(families: &HashMap<String, Vec<FamilyMember>>, name: &str) -> ResolvedFamily {
+    let mut resolved = ResolvedFamily::default();
+
+    expand_into(families, name, &mut vec![], &mut resolved);
+    resolved
+}
+
+/// 같은 collection이 여러 family에 들어 있으면 한 번만 복사하고, 먼저 나온 query를 씁니다.
+fn expand_into(
+    families: &HashMap<String, Vec<FamilyMember>>,
+    name: &str,
+    stack: &mut Vec<String>,
+    resolved: &mut ResolvedFamily,
+) {
+    if stack.iter().any(|family| family == name) {
+        exit_with_error!(
+            "family {} references itself: {} -> {}",
+            name,
+            stack.join(" -> "),
+            name
+        )
+    }
+
+    let members = families
+        .get(name)
+        .unwrap_or_else(|| exit_with_error!("family {} not found in config", name));
+
+    stack.push(name.to_string());
+    for member in members {
+        let (collection, query) = match member {
+            FamilyMember::Family { family } => {
+                expand_into(families, family, stack, resolved);
+                continue;
+            }
+            FamilyMember::Collection(collection) => (collection, None),
+            FamilyMember::Filtered { collection, query } => (collection, Some(query.to_string())),
+        };
+
+        if !resolved.collections.contains(collection) {
+            resolved.collections.push(collection.clone());
+        }
+        if let Some(query) = query {
+            resolved.queries.entry(collection.clone()).or_insert(query);
+        }
+    }
+    stack.pop();
+}
+
+/// (collection, field)
+type Endpoint<'a> = (&'a str, &'a str);
+
+/// `rounds_lectures.round_id`를 `("rounds_lectures", "round_id")`로 나눕니다.
+fn endpoint(link: &str) -> Endpoint<'_> {
+    link.split_once('.').unwrap_or_else(|| {
+        exit_with_error!("link endpoint {} must look like collection.field", link)
+    })
+}
+
+/// 복사할 collection끼리 이어진 link만 남깁니다.
+fn links_between<'a>(
+    collections: &[String],
+    links: &'a [CollectionLink],
+) -> Vec<(Endpoint<'a>, Endpoint<'a>)> {
+    links
+        .iter()
+        .map(|link| (endpoint(&link.from), endpoint(&link.to)))
+        .filter(|((from, _), (to, _))| {
+            collections.iter().any(|col| col == from) && collections.iter().any(|col| col == to)
+        })
+        .collect()
+}
+
+/// `narrow`가 source에 query를 보내 query를 채우게 되는지
+pub fn needs_narrowing(
+    collections: &[String],
+    queries: &HashMap<String, String>,
+    links: &[CollectionLink],
+) -> bool {
+    !queries.is_empty() && !links_between(collections, links).is_empty()
+}
+
+/// `field`의 서로 다른 값. `distinct`는 결과 전체가 16MB를 넘으면 실패하므로 aggregation cursor로 하나씩 받습니다.
+/// `distinct`처럼 배열은 원소마다 값으로 보고, 값이 없거나 null인 document는 건너뜁니다.
+async fn distinct_values(
+    db: &Database,
+    collection: &str,
+    field: &str,
+    filter: Document,
+) -> mongodb::error::Result<Vec<Bson>> {
+    let path = format!("${}", field);
+    let mut cursor = db
+        .collection::<Document>(collection)
+        .aggregate([
+            doc! { "$match": filter },
+            doc! { "$unwind": &path },
+            doc! { "$group": { "_id": &path } },
+        ])
+        .allow_disk_use(true)
+        .await?;
+    let mut values = vec![];
+
+    while let Some(mut group) = cursor.try_next().await? {
+        if let Some(value) = group.remove("_id") {
+            values.push(value);
+        }
+    }
+
+    Ok(values)
+}
+
+/// query가 있는 collection에서 시작해 link를 따라가며, 연결된 collection은 참조하거나 참조되는 document만
+/// 복사하도록 query를 채웁니다. 이미 query가 있는 collection은 그대로 둡니다.
+pub async fn narrow(
+    source_uri: &str,
+    db_name: &str,
+    collections: &[String],
+    queries: &mut HashMap<String, String>,
+    links: &[CollectionLink],
+) {
+    let links = links_between(collections, links);
+
+    if links.is_empty() || queries.is_empty() {
+        return;
+    }
+
+    let source_db = native::connect(source_uri, db_name).await;
+    let mut visited: HashSet<String> = queries.keys().cloned().collect();
+    let mut queue: VecDeque<String> = collections
+        .iter()
+        .filter(|col| queries.contains_key(*col))
+        .cloned()
+        .collect();
+
+    while let Some(collection) = queue.pop_front() {
+        let filter = native::parse_query(&collection, &queries[&collection]);
+
+        for (from, to) in &links {
+            let ((_, own_field), (other, other_field)) = if from.0 == collection {
+                (from, to)
+            } else if to.0 == collection {
+                (to, from)
+            } else {
+                continue;
+            };
+            if visited.contains(*other) {
+                continue;
+            }
+
+            let values = distinct_values(&source_db, &collection, own_field, filter.clone())
+                .await
+                .unwrap_or_else(|e| {
+                    exit_with_error!("failed to read {}.{}:\n{}", collection, own_field, e)
+                });
+            let query = Bson::Document(doc! { *other_field: { "$in": values } });
+
+            queries.insert(other.to_string(), query.into_relaxed_extjson().to_string());
+            visited.insert(other.to_string());
+            queue.push_back(other.to_string());
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use super::{
+    masking::Masker,
+    native::{self, Selection, WriteMode},
+    report::CollectionReport,
+    DumpPlan,
+};
+use crate::{cli::FixtureFormat, exit_with_error};
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document, RawDocumentBuf},
+    Database,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+const EXTENSIONS: [&str; 3] = ["ndjson", "json", "ejson"];
+const BATCH_DOCUMENTS: usize = 1000;
+
+fn extension(format: &FixtureFormat) -> &'static str {
+    match format {
+        FixtureFormat::Ndjson => "ndjson",
+        FixtureFormat::Json => "json",
+        FixtureFormat::Ejson => "ejson",
+    }
+}
+
+/// view는 import할 때 일반 collection이 되어 버리므로 내보내지 않습니다.
+async fn collection_names(source_db: &Database) -> mongodb::error::Result<Vec<String>> {
+    let mut names: Vec<String> = source_db
+        .list_collection_names()
+        .filter(doc! { "type": "collection" })
+        .await?
+        .into_iter()
+        .filter(|name| !name.starts_with("system."))
+        .collect();
+
+    names.sort();
+    Ok(names)
+}
+
+/// document를 모두 읽어 파일 하나로 씁니다. fixture는 작다고 보고 한 번에 메모리에 올립니다.
+async fn export_collection(
+    source_db: &Database,
+    name: &str,
+    selection: &Selection,
+    masker: &Masker,
+    format: &FixtureFormat,
+    path: &Path,
+) -> Result<(u64, u64), String> {
+    let source = source_db.collection::<RawDocumentBuf>(name);
+    let mut cursor = selection.cursor(&source).await.map_err(|e| e.to_string())?;
+    let mut documents = vec![];
+
+    while let Some(raw) = cursor.try_next().await.map_err(|e| e.to_string())? {
+        let mut document = raw.to_document().map_err(|e| e.to_string())?;
+
+        masker.apply(name, &mut document);
+        documents.push(match format {
+            FixtureFormat::Ejson => Bson::Document(document).into_canonical_extjson(),
+            FixtureFormat::Ndjson | FixtureFormat::Json => {
+                Bson::Document(document).into_relaxed_extjson()
+            }
+        });
+    }
+
+    let content = match format {
+        FixtureFormat::Ndjson => documents
+            .iter()
+            .map(|document| format!("{}\n", document))
+            .collect(),
+        FixtureFormat::Json | FixtureFormat::Ejson => {
+            serde_json::to_string_pretty(&documents).map_err(|e| e.to_string())? + "\n"
+        }
+    };
+
+    fs::write(path, &content).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+
+    Ok((documents.len() as u64, content.len() as u64))
+}
+
+/// collection마다 `{dir}/{collection}.{ndjson,json,ejson}` 파일을 만듭니다.
+pub async fn export(plan: &DumpPlan, format: &FixtureFormat, dir: &Path) -> Vec<CollectionReport> {
+    fs::create_dir_all(dir)
+        .unwrap_or_else(|e| exit_with_error!("failed to create {}:\n{}", dir.display(), e));
+
+    let source_db = native::connect(&plan.source_uri, &plan.source_db).await;
+    let collections = match &plan.collections {
+        Some(cols) => cols.clone(),
+        None => collection_names(&source_db)
+            .await
+            .unwrap_or_else(|e| exit_with_error!("failed to list collections:\n{}", e))
+            .into_iter()
+            .filter(|col| !plan.excludes.contains(col))
+            .collect(),
+    };
+    let filters: HashMap<&str, Document> = plan
+        .queries
+        .iter()
+        .map(|(col, query)| (col.as_str(), native::parse_query(col, query)))
+        .collect();
+    let masker = Masker::new(&plan.mask_seed, &plan.masking);
+    let mut reports = vec![];
+
+    for name in collections {
+        let started_at = Instant::now();
+        let selection = Selection {
+            filter: filters.get(name.as_str()).cloned().unwrap_or_default(),
+            limit: plan.limit,
+            sample: plan.sample,
+        };
+        let path = dir.join(format!("{}.{}", name, extension(format)));
+        let exported =
+            export_collection(&source_db, &name, &selection, &masker, format, &path).await;
+        let (documents, bytes) = exported.as_ref().map_or((0, 0), |counts| *counts);
+
+        reports.push(CollectionReport {
+            name,
+            documents,
+            bytes: Some(bytes),
+            elapsed: started_at.elapsed(),
+            attempts: 1,
+            error: exported.err(),
+        });
+    }
+
+    reports
+}
+
+/// `dir` 바로 아래의 fixture 파일을 collection 이름 순으로 돌려줍니다.
+pub fn fixture_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut files: Vec<(String, PathBuf)> = fs::read_dir(dir)
+        .unwrap_or_else(|e| exit_with_error!("failed to read {}:\n{}", dir.display(), e))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| EXTENSIONS.iter().any(|known| ext == *known))
+        })
+        .filter_map(|path| {
+            let collection = path.file_stem()?.to_string_lossy().to_string();
+
+            Some((collection, path))
+        })
+        .collect();
+
+    if files.is_empty() {
+        exit_with_error!("no .ndjson/.json/.ejson fixture in {}", dir.display())
+    }
+    files.sort();
+    files
+}
+
+fn parse_document(path: &Path, value: serde_json::Value) -> Result<RawDocumentBuf, String> {
+    match Bson::try_from(value) {
+        Ok(Bson::Document(document)) => {
+            RawDocumentBuf::from_document(&document).map_err(|e| e.to_string())
+        }
+        Ok(_) => Err(format!(
+            "{} contains a value that is not a document",
+            path.display()
+        )),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// ndjson은 한 줄에 document 하나, json/ejson은 document 배열입니다.
+fn read_documents(path: &Path) -> Result<Vec<RawDocumentBuf>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+    if path.extension().is_some_and(|ext| ext == "ndjson") {
+        return content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+                    .and_then(|value| parse_document(path, value))
+            })
+            .collect();
+    }
+
+    match serde_json::from_str(&content) {
+        Ok(serde_json::Value::Array(values)) => values
+            .into_iter()
+            .map(|value| parse_document(path, value))
+            .collect(),
+        Ok(_) => Err(format!("{} is not a JSON array", path.display())),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+async fn import_collection(
+    target_db: &Database,
+    name: &str,
+    path: &Path,
+    mode: WriteMode,
+) -> Result<(u64, u64), String> {
+    let documents = read_documents(path)?;
+    let bytes = documents
+        .iter()
+        .map(|document| document.as_bytes().len() as u64)
+        .sum();
+    let target = target_db.collection::<RawDocumentBuf>(name);
+    let mut inserted = 0;
+
+    if let WriteMode::Replace = mode {
+        target.drop().await.map_err(|e| e.to_string())?;
+    }
+    for chunk in documents.chunks(BATCH_DOCUMENTS) {
+        let mut batch = chunk.to_vec();
+
+        inserted += native::flush(&target, &mut batch, mode)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok((inserted, bytes))
+}
+
+pub async fn import(plan: &DumpPlan, files: &[(String, PathBuf)]) -> Vec<CollectionReport> {
+    let target_db = native::connect(&plan.target_uri, &plan.target_db).await;
+    let mode = if plan.drop {
+        WriteMode::Replace
+    } else {
+        WriteMode::Merge
+    };
+    let mut reports = vec![];
+
+    for (name, path) in files {
+        let started_at = Instant::now();
+        let imported = import_collection(&target_db, name, path, mode).await;
+        let (documents, bytes) = imported.as_ref().map_or((0, 0), |counts| *counts);
+
+        reports.push(CollectionReport {
+            name: name.clone(),
+            documents,
+            bytes: Some(bytes),
+            elapsed: started_at.elapsed(),
+            attempts: 1,
+            error: imported.err(),
+        });
+    }
+
+    reports
+}
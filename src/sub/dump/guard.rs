@@ -0,0 +1,175 @@
+use super::{mask_uri, DumpPlan};
+use crate::config::TargetGuard;
+use crate::exit_with_error;
+use colored::Colorize;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// `mongodb://user:pw@host1:27017,host2/db?opts`에서 host 이름만 소문자로 꺼냅니다.
+fn hosts(uri: &str) -> Vec<String> {
+    let rest = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    let hosts = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, hosts)| hosts);
+
+    hosts
+        .split(',')
+        .map(|host| host.split(':').next().unwrap_or(host).to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// `*.example.com`처럼 앞에 `*.`을 붙이면 하위 도메인 전체와 맞습니다.
+fn matches_host(host: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.to_lowercase();
+
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+            None => host == pattern,
+        }
+    })
+}
+
+/// 복원하면 안 되는 target이면 그 이유. source와 같은 host는 `same_cluster_hosts`에 적었을 때만 허용합니다.
+pub(super) fn target_error(plan: &DumpPlan, target_guard: &TargetGuard) -> Option<String> {
+    let target_hosts = hosts(&plan.target_uri);
+
+    if let Some(host) = target_hosts
+        .iter()
+        .find(|host| matches_host(host, &target_guard.protected_hosts))
+    {
+        return Some(format!(
+            "target host {} is protected; refusing to restore into it",
+            host
+        ));
+    }
+
+    let source_hosts = hosts(&plan.source_uri);
+    if let Some(host) = target_hosts.iter().find(|host| {
+        source_hosts.contains(host) && !matches_host(host, &target_guard.same_cluster_hosts)
+    }) {
+        return Some(format!(
+            "target {} is on the source cluster ({}); refusing to restore into it unless the host is listed in same_cluster_hosts",
+            plan.target_db, host
+        ));
+    }
+
+    None
+}
+
+/// source cluster이거나 보호 대상 host로 복원하려 하면 종료합니다.
+pub fn check_target(plan: &DumpPlan, target_guard: &TargetGuard) {
+    if let Some(error) = target_error(plan, target_guard) {
+        exit_with_error!("{} {}", "✘".red(), error)
+    }
+}
+
+/// drop될 collection 목록을 보여주고 사용자에게 확인을 받습니다. `--yes`면 묻지 않습니다.
+pub fn confirm_drop(plan: &DumpPlan, yes: bool) {
+    if !plan.drop || yes {
+        return;
+    }
+
+    eprintln!(
+        "{} the following collections in {} on {} will be dropped and replaced:",
+        "!".yellow().bold(),
+        plan.target_db.bold(),
+        mask_uri(&plan.target_uri)
+    );
+    match &plan.collections {
+        Some(cols) => cols.iter().for_each(|col| eprintln!("  - {}", col)),
+        None => eprintln!(
+            "  - every collection restored from {} ({} excluded)",
+            plan.source_db,
+            plan.excludes.len()
+        ),
+    }
+
+    ask_to_continue();
+}
+
+/// 지금까지 보여준 내용으로 계속할지 묻고, 아니라고 하면 종료합니다.
+pub fn ask_to_continue() {
+    if !io::stdin().is_terminal() {
+        exit_with_error!("not a terminal; pass --yes to confirm or --no-drop to merge")
+    }
+
+    eprint!("continue? [y/N] ");
+    io::stderr().flush().expect("failed to flush stderr");
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .expect("failed to read answer");
+
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        exit_with_error!("aborted")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(source_uri: &str, target_uri: &str, source_db: &str, target_db: &str) -> DumpPlan {
+        DumpPlan {
+            source_uri: source_uri.to_string(),
+            target_uri: target_uri.to_string(),
+            source_db: source_db.to_string(),
+            target_db: target_db.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn extracts_hosts_from_uri() {
+        assert_eq!(hosts("mongodb://localhost:27017"), vec!["localhost"]);
+        assert_eq!(
+            hosts("mongodb://user:p@ss@Host1:27017,host2/db?replicaSet=rs"),
+            vec!["host1", "host2"]
+        );
+        assert_eq!(
+            hosts("mongodb+srv://user:pw@cluster.example.com/?retryWrites=true"),
+            vec!["cluster.example.com"]
+        );
+        assert_eq!(
+            hosts("postgres://dev@db.local:5432?sslmode=require"),
+            vec!["db.local"]
+        );
+    }
+
+    #[test]
+    fn matches_hosts_and_wildcards() {
+        let patterns = vec!["prod.example.com".to_string(), "*.mongodb.net".to_string()];
+
+        assert!(matches_host("prod.example.com", &patterns));
+        assert!(matches_host("mongodb.net", &patterns));
+        assert!(matches_host("online.abc.mongodb.net", &patterns));
+        assert!(!matches_host("dev.example.com", &patterns));
+        assert!(!matches_host("evilmongodb.net", &patterns));
+    }
+
+    #[test]
+    fn refuses_the_source_cluster_unless_allowlisted() {
+        let uri = "mongodb://localhost:27017";
+        let guard = TargetGuard::default();
+
+        assert!(target_error(&plan(uri, uri, "app", "app_dev"), &guard).is_some());
+        assert!(target_error(&plan(uri, uri, "app", "app"), &guard).is_some());
+        assert!(target_error(&plan(uri, "mongodb://other:27017", "app", "app"), &guard).is_none());
+
+        let allowlisted = TargetGuard {
+            same_cluster_hosts: vec!["localhost".to_string()],
+            ..Default::default()
+        };
+        assert!(target_error(&plan(uri, uri, "app", "app_dev"), &allowlisted).is_none());
+
+        let protected = TargetGuard {
+            protected_hosts: vec!["localhost".to_string()],
+            same_cluster_hosts: vec!["localhost".to_string()],
+        };
+        assert!(target_error(&plan(uri, uri, "app", "app_dev"), &protected).is_some());
+    }
+}
@@ -0,0 +1,256 @@
+use super::{report::CollectionReport, DumpPlan};
+use crate::exit_with_error;
+use chrono::{DateTime, Local};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, create_dir_all, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+const HISTORY_SUFFIX: &str = "sprt/dump_history.jsonl";
+/// `sprt dump schedule`이 띄운 dump에 실행 계기(`schedule:{cron}`)를 넘겨 주는 환경 변수
+pub const TRIGGER_ENV: &str = "SPRT_DUMP_TRIGGER";
+
+/// `sprt dump` 한 번에서 database 하나를 복사한 기록
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryEntry {
+    pub started_at: String,
+    pub service: String,
+    pub engine: String,
+    pub source_db: String,
+    pub target_db: String,
+    pub collections: Vec<String>,
+    pub failed: Vec<String>,
+    pub documents: u64,
+    pub elapsed_secs: f64,
+    /// `manual` 또는 `schedule:{cron}`
+    pub trigger: String,
+    /// collection을 복사하기 전에 실패한 이유. schedule이 띄운 dump가 기록 없이 실패했을 때 채웁니다.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// `--to-file`로 저장한 archive 경로
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        plan: &DumpPlan,
+        engine: &str,
+        started_at: DateTime<Local>,
+        elapsed: Duration,
+        reports: &[CollectionReport],
+    ) -> HistoryEntry {
+        HistoryEntry {
+            started_at: started_at.to_rfc3339(),
+            service: plan.service.clone(),
+            engine: engine.to_string(),
+            source_db: plan.source_db.clone(),
+            target_db: plan.target_db.clone(),
+            collections: reports.iter().map(|report| report.name.clone()).collect(),
+            failed: reports
+                .iter()
+                .filter(|report| !report.succeeded())
+                .map(|report| report.name.clone())
+                .collect(),
+            documents: reports.iter().map(|report| report.documents).sum(),
+            elapsed_secs: elapsed.as_secs_f64(),
+            trigger: trigger(),
+            error: None,
+            archive: None,
+        }
+    }
+
+    /// `--to-file` 한 번의 기록. archive에는 collection별 document 수가 없으므로 0으로 둡니다.
+    pub fn archive(
+        plan: &DumpPlan,
+        path: &Path,
+        started_at: DateTime<Local>,
+        elapsed: Duration,
+        result: &Result<u64, String>,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            started_at: started_at.to_rfc3339(),
+            service: plan.service.clone(),
+            engine: "tools".to_string(),
+            source_db: plan.source_db.clone(),
+            target_db: String::new(),
+            collections: plan.collections.clone().unwrap_or_default(),
+            failed: vec![],
+            documents: 0,
+            elapsed_secs: elapsed.as_secs_f64(),
+            trigger: trigger(),
+            error: result.as_ref().err().cloned(),
+            archive: Some(path.display().to_string()),
+        }
+    }
+
+    /// 기록을 남기지 못하고 끝난 dump. `schedule`이 실패한 실행을 대신 기록할 때 씁니다.
+    pub fn failed_run(
+        service: &str,
+        trigger: &str,
+        started_at: DateTime<Local>,
+        elapsed: Duration,
+        error: String,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            started_at: started_at.to_rfc3339(),
+            service: service.to_string(),
+            engine: String::new(),
+            source_db: String::new(),
+            target_db: String::new(),
+            collections: vec![],
+            failed: vec![],
+            documents: 0,
+            elapsed_secs: elapsed.as_secs_f64(),
+            trigger: trigger.to_string(),
+            error: Some(error),
+            archive: None,
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.failed.is_empty() && self.error.is_none()
+    }
+
+    fn namespace(&self) -> String {
+        match (&self.archive, self.source_db.is_empty()) {
+            (Some(path), _) => format!("{}.* > {}", self.source_db, path),
+            (None, true) => "-".to_string(),
+            (None, false) => format!("{}.* -> {}.*", self.source_db, self.target_db),
+        }
+    }
+}
+
+fn trigger() -> String {
+    std::env::var(TRIGGER_ENV).unwrap_or_else(|_| "manual".to_string())
+}
+
+/// `trigger`로 실행되어 실패로 남은 기록 수
+pub fn failed_count(trigger: &str) -> usize {
+    load()
+        .iter()
+        .filter(|entry| entry.trigger == trigger && !entry.succeeded())
+        .count()
+}
+
+fn history_path() -> PathBuf {
+    dirs::home_dir()
+        .expect("failed to get home dir")
+        .join(HISTORY_SUFFIX)
+}
+
+/// 한 줄에 기록 하나씩 `~/sprt/dump_history.jsonl` 끝에 덧붙입니다.
+/// 기록하지 못해도 dump 결과에는 영향이 없으므로 경고만 남깁니다.
+pub fn append(entry: &HistoryEntry) {
+    let path = history_path();
+    let parent = path.parent().expect("failed to get parent dir path");
+    let line = serde_json::to_string(entry).expect("failed to serialize history entry");
+    let written = create_dir_all(parent).and_then(|_| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+    });
+
+    if let Err(e) = written {
+        eprintln!(
+            "{} failed to record dump history in {}: {}",
+            "!".yellow(),
+            path.display(),
+            e
+        );
+    }
+}
+
+/// 오래된 기록부터 돌려줍니다. 읽을 수 없는 줄은 건너뜁니다.
+pub fn load() -> Vec<HistoryEntry> {
+    let path = history_path();
+
+    if !path.exists() {
+        return vec![];
+    }
+
+    fs::read_to_string(&path)
+        .unwrap_or_else(|e| exit_with_error!("failed to read {}:\n{}", path.display(), e))
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+pub fn print_entries(entries: &[&HistoryEntry]) {
+    if entries.is_empty() {
+        println!("no matching dump history in {}", history_path().display());
+        return;
+    }
+
+    let header = [
+        "started at",
+        "service",
+        "namespace",
+        "collections",
+        "documents",
+        "elapsed",
+        "trigger",
+        "status",
+    ];
+    let rows: Vec<[String; 8]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                DateTime::parse_from_rfc3339(&entry.started_at)
+                    .map_or(entry.started_at.clone(), |started_at| {
+                        started_at.format("%Y-%m-%d %H:%M:%S").to_string()
+                    }),
+                entry.service.clone(),
+                entry.namespace(),
+                entry.collections.len().to_string(),
+                entry.documents.to_string(),
+                format!("{:.1?}", Duration::from_secs_f64(entry.elapsed_secs)),
+                entry.trigger.clone(),
+                match (&entry.error, entry.failed.len()) {
+                    (Some(_), _) => "error".to_string(),
+                    (None, 0) => "ok".to_string(),
+                    (None, failed) => format!("{} failed", failed),
+                },
+            ]
+        })
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|col| {
+            rows.iter()
+                .map(|row| row[col].chars().count())
+                .chain(std::iter::once(header[col].len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let pad = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(col, (cell, width))| match col {
+                3..=5 => format!("{:>width$}", cell, width = width),
+                _ => format!("{:<width$}", cell, width = width),
+            })
+            .collect::<Vec<String>>()
+    };
+    let header_line = pad(&header.map(String::from)).join("  ");
+
+    println!("{}", header_line.bold());
+    println!("{}", "-".repeat(header_line.chars().count()));
+
+    for (entry, row) in entries.iter().zip(&rows) {
+        let cells = pad(row);
+        let status = if entry.succeeded() {
+            cells[7].green()
+        } else {
+            cells[7].red()
+        };
+
+        println!("{}  {}", cells[..7].join("  "), status);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::config::MaskRule;
+use mongodb::bson::{Bson, Document};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+const FAMILY_NAMES: [&str; 20] = [
+    "김", "이", "박", "최", "정", "강", "조", "윤", "장", "임", "한", "오", "서", "신", "권", "황",
+    "안", "송", "류", "홍",
+];
+const GIVEN_NAME_SYLLABLES: [&str; 24] = [
+    "민", "서", "준", "지", "현", "우", "은", "하", "도", "윤", "예", "진", "수", "연", "호", "유",
+    "아", "재", "원", "승", "다", "시", "주", "영",
+];
+
+/// collection별 masking 규칙을 document에 적용합니다.
+/// 같은 seed와 같은 원본 값이면 항상 같은 결과가 나오므로 collection 사이의 join이 유지됩니다.
+pub struct Masker {
+    seed: String,
+    rules: HashMap<String, Vec<(Vec<String>, MaskRule)>>,
+}
+
+impl Masker {
+    pub fn new(seed: &str, rules: &HashMap<String, HashMap<String, MaskRule>>) -> Masker {
+        let rules = rules
+            .iter()
+            .map(|(collection, fields)| {
+                let fields = fields
+                    .iter()
+                    .map(|(path, rule)| (path.split('.').map(String::from).collect(), *rule))
+                    .collect();
+
+                (collection.clone(), fields)
+            })
+            .collect();
+
+        Masker {
+            seed: seed.to_string(),
+            rules,
+        }
+    }
+
+    pub fn applies_to(&self, collection: &str) -> bool {
+        self.rules.get(collection).is_some_and(|fields| {
+            fields
+                .iter()
+                .any(|(_, rule)| !matches!(rule, MaskRule::Keep))
+        })
+    }
+
+    pub fn apply(&self, collection: &str, document: &mut Document) {
+        let Some(fields) = self.rules.get(collection) else {
+            return;
+        };
+
+        for (path, rule) in fields {
+            mask_path(document, path, &|value| self.mask_value(*rule, value));
+        }
+    }
+
+    fn digest(&self, value: &Bson) -> Vec<u8> {
+        let original = match value {
+            Bson::String(string) => string.clone(),
+            other => other.clone().into_relaxed_extjson().to_string(),
+        };
+
+        Sha256::new()
+            .chain_update(self.seed.as_bytes())
+            .chain_update([0])
+            .chain_update(original.as_bytes())
+            .finalize()
+            .to_vec()
+    }
+
+    fn mask_value(&self, rule: MaskRule, value: &Bson) -> Bson {
+        // 비어 있는 값은 가릴 정보가 없으므로 그대로 둡니다.
+        if let Bson::Null | Bson::Undefined = value {
+            return value.clone();
+        }
+
+        match rule {
+            MaskRule::Keep => value.clone(),
+            MaskRule::Null => Bson::Null,
+            MaskRule::Hash => Bson::String(hex(&self.digest(value))),
+            MaskRule::FakeName => {
+                let digest = self.digest(value);
+                let family_name = FAMILY_NAMES[digest[0] as usize % FAMILY_NAMES.len()];
+                let given_name: String = digest[1..3]
+                    .iter()
+                    .map(|byte| GIVEN_NAME_SYLLABLES[*byte as usize % GIVEN_NAME_SYLLABLES.len()])
+                    .collect();
+
+                Bson::String(format!("{}{}", family_name, given_name))
+            }
+            MaskRule::FakePhone => {
+                let digits: String = self
+                    .digest(value)
+                    .iter()
+                    .take(8)
+                    .map(|byte| char::from(b'0' + byte % 10))
+                    .collect();
+
+                Bson::String(format!("010-{}-{}", &digits[..4], &digits[4..]))
+            }
+            MaskRule::FakeEmail => Bson::String(format!(
+                "user_{}@example.com",
+                &hex(&self.digest(value))[..12]
+            )),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 배열 안의 document까지 따라 내려가며 `path`에 해당하는 값을 바꿉니다.
+fn mask_path(document: &mut Document, path: &[String], mask: &dyn Fn(&Bson) -> Bson) {
+    let Some((key, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(value) = document.get_mut(key) else {
+        return;
+    };
+
+    if rest.is_empty() {
+        match value {
+            Bson::Array(items) => items.iter_mut().for_each(|item| *item = mask(item)),
+            _ => *value = mask(value),
+        }
+        return;
+    }
+
+    match value {
+        Bson::Document(nested) => mask_path(nested, rest, mask),
+        Bson::Array(items) => items.iter_mut().for_each(|item| {
+            if let Bson::Document(nested) = item {
+                mask_path(nested, rest, mask)
+            }
+        }),
+        _ => {}
+    }
+}
@@ -0,0 +1,602 @@
+use super::{
+    masking::Masker,
+    progress,
+    report::{format_bytes, CollectionReport},
+    sync_state::{self, Checkpoint, SyncState},
+    throttle::{self, RateLimiter},
+    DumpPlan,
+};
+use crate::exit_with_error;
+use futures_util::TryStreamExt;
+use indicatif::{MultiProgress, ProgressBar};
+use mongodb::{
+    bson::{doc, Bson, Document, RawDocumentBuf},
+    error::{ErrorKind, InsertManyError},
+    results::{CollectionSpecification, CollectionType},
+    Client, Collection, Cursor, Database, IndexModel,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+// insert_many 한 번에 보내는 양. 서버의 16MB 메시지 제한보다 충분히 작게 잡습니다.
+const BATCH_DOCUMENTS: usize = 1000;
+const BATCH_BYTES: usize = 8 * 1024 * 1024;
+const DUPLICATE_KEY: i32 = 11000;
+
+pub async fn connect(uri: &str, db_name: &str) -> Database {
+    Client::with_uri_str(uri)
+        .await
+        .unwrap_or_else(|e| exit_with_error!("failed to connect mongodb:\n{}", e))
+        .database(db_name)
+}
+
+/// document를 target collection에 쓰는 방식
+#[derive(Clone, Copy)]
+pub enum WriteMode {
+    /// mongorestore --drop처럼 collection을 지우고 새로 넣습니다.
+    Replace,
+    /// mongorestore를 `--drop` 없이 실행했을 때처럼 이미 있는 `_id`는 건너뜁니다.
+    Merge,
+    /// 이미 있는 `_id`는 source의 document로 덮어씁니다(`--incremental`).
+    Upsert,
+}
+
+pub async fn flush(
+    target: &Collection<RawDocumentBuf>,
+    batch: &mut Vec<RawDocumentBuf>,
+    mode: WriteMode,
+) -> mongodb::error::Result<u64> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    if let WriteMode::Upsert = mode {
+        let ids = batch
+            .iter()
+            .filter_map(|document| document.get("_id").ok().flatten())
+            .map(|id| Bson::try_from(id.to_raw_bson()))
+            .collect::<Result<Vec<Bson>, _>>()?;
+
+        target.delete_many(doc! { "_id": { "$in": ids } }).await?;
+    }
+
+    let merge = matches!(mode, WriteMode::Merge);
+    let inserted = match target.insert_many(batch.iter()).ordered(!merge).await {
+        Ok(result) => result.inserted_ids.len() as u64,
+        Err(e) if merge => match e.kind.as_ref() {
+            ErrorKind::InsertMany(InsertManyError {
+                write_errors: Some(write_errors),
+                write_concern_error: None,
+                ..
+            }) if write_errors.iter().all(|error| error.code == DUPLICATE_KEY) => {
+                (batch.len() - write_errors.len()) as u64
+            }
+            _ => return Err(e),
+        },
+        Err(e) => return Err(e),
+    };
+    batch.clear();
+
+    Ok(inserted)
+}
+
+/// collection에서 어떤 document를 복사할지
+pub struct Selection {
+    pub filter: Document,
+    pub limit: Option<u64>,
+    pub sample: Option<u64>,
+}
+
+impl Selection {
+    async fn count(&self, source: &Collection<RawDocumentBuf>) -> u64 {
+        let total = if self.filter.is_empty() {
+            source.estimated_document_count().await
+        } else {
+            source.count_documents(self.filter.clone()).await
+        }
+        .unwrap_or_default();
+
+        self.limit
+            .or(self.sample)
+            .map_or(total, |size| size.min(total))
+    }
+
+    pub async fn cursor(
+        &self,
+        source: &Collection<RawDocumentBuf>,
+    ) -> mongodb::error::Result<Cursor<RawDocumentBuf>> {
+        if let Some(size) = self.sample {
+            return source
+                .aggregate(vec![
+                    doc! { "$match": self.filter.clone() },
+                    doc! { "$sample": { "size": size as i64 } },
+                ])
+                .with_type::<RawDocumentBuf>()
+                .await;
+        }
+
+        let find = source.find(self.filter.clone());
+
+        match self.limit {
+            Some(limit) => find.limit(limit as i64).await,
+            None => find.await,
+        }
+    }
+}
+
+pub fn parse_query(collection: &str, query: &str) -> Document {
+    serde_json::from_str::<serde_json::Value>(query)
+        .ok()
+        .and_then(|value| Bson::try_from(value).ok())
+        .and_then(|bson| bson.as_document().cloned())
+        .unwrap_or_else(|| {
+            exit_with_error!(
+                "query for {} is not a valid extended JSON document: {}",
+                collection,
+                query
+            )
+        })
+}
+
+/// `--incremental`에서 collection 하나를 어디서부터 복사할지
+struct Incremental {
+    field: String,
+    since: Option<Bson>,
+}
+
+#[derive(Default)]
+struct Transferred {
+    documents: u64,
+    bytes: u64,
+}
+
+/// 모든 collection 복사에 공통으로 쓰는 연결과 설정
+struct Copier {
+    source_db: Database,
+    target_db: Database,
+    masker: Masker,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Copier {
+    async fn copy_collection(
+        &self,
+        spec: &CollectionSpecification,
+        selection: &Selection,
+        mode: WriteMode,
+        bar: &ProgressBar,
+        transferred: &mut Transferred,
+    ) -> mongodb::error::Result<()> {
+        let Copier {
+            source_db,
+            target_db,
+            masker,
+            limiter,
+        } = self;
+        let source = source_db.collection::<RawDocumentBuf>(&spec.name);
+        let target = target_db.collection::<RawDocumentBuf>(&spec.name);
+        let drop = matches!(mode, WriteMode::Replace);
+
+        // mongorestore --drop과 동일하게 target collection을 지우고 source의 옵션 그대로 다시 만듭니다.
+        // drop하지 않을 때는 collection이 없을 때만 만듭니다.
+        if drop {
+            target.drop().await?;
+        }
+        let exists = !drop
+            && !target_db
+                .list_collection_names()
+                .filter(doc! { "name": &spec.name })
+                .await?
+                .is_empty();
+        if !exists {
+            target_db
+                .create_collection(&spec.name)
+                .with_options(spec.options.clone())
+                .await?;
+        }
+
+        if let CollectionType::View = spec.collection_type {
+            return Ok(());
+        }
+
+        bar.set_length(selection.count(&source).await);
+
+        let indexes: Vec<IndexModel> = source
+            .list_indexes()
+            .await?
+            .try_collect::<Vec<IndexModel>>()
+            .await?
+            .into_iter()
+            .filter(|index| {
+                index.options.as_ref().and_then(|opts| opts.name.as_deref()) != Some("_id_")
+            })
+            .collect();
+
+        let mut cursor = selection.cursor(&source).await?;
+        let masked = masker.applies_to(&spec.name);
+        let batch_documents = limiter.as_ref().map_or(BATCH_DOCUMENTS, |limiter| {
+            limiter.batch_documents(BATCH_DOCUMENTS)
+        });
+        let mut batch = Vec::with_capacity(batch_documents);
+        let mut batch_bytes = 0;
+
+        while let Some(mut document) = cursor.try_next().await? {
+            if masked {
+                let mut parsed = document.to_document()?;
+
+                masker.apply(&spec.name, &mut parsed);
+                document = RawDocumentBuf::from_document(&parsed)?;
+            }
+
+            batch_bytes += document.as_bytes().len();
+            batch.push(document);
+
+            if batch.len() >= batch_documents || batch_bytes >= BATCH_BYTES {
+                if let Some(limiter) = limiter {
+                    tokio::time::sleep(limiter.delay(batch.len() as u64, batch_bytes as u64)).await;
+                }
+                let inserted = flush(&target, &mut batch, mode).await?;
+
+                transferred.documents += inserted;
+                transferred.bytes += batch_bytes as u64;
+                bar.inc(inserted);
+                bar.set_message(format_bytes(transferred.bytes));
+                batch_bytes = 0;
+            }
+        }
+        let inserted = flush(&target, &mut batch, mode).await?;
+
+        transferred.documents += inserted;
+        transferred.bytes += batch_bytes as u64;
+        bar.inc(inserted);
+        bar.set_message(format_bytes(transferred.bytes));
+
+        // mongorestore처럼 document를 모두 넣은 뒤 index를 만듭니다.
+        if !indexes.is_empty() {
+            target.create_indexes(indexes).await?;
+        }
+
+        Ok(())
+    }
+
+    /// source에 지금 있는 가장 큰 `field` 값까지만 복사하고, 그 값을 다음 동기화 지점으로 돌려줍니다.
+    /// 복사하는 동안 새로 들어온 document는 다음 실행에서 가져갑니다.
+    async fn sync_collection(
+        &self,
+        spec: &CollectionSpecification,
+        selection: &Selection,
+        incremental: &Incremental,
+        bar: &ProgressBar,
+        transferred: &mut Transferred,
+    ) -> mongodb::error::Result<Option<Bson>> {
+        let Incremental { field, since } = incremental;
+        let latest = self
+            .source_db
+            .collection::<Document>(&spec.name)
+            .find_one(selection.filter.clone())
+            .sort(doc! { field: -1 })
+            .projection(doc! { field: 1 })
+            .await?
+            .and_then(|document| document.get(field).cloned());
+
+        let mut range = Document::new();
+        if let Some(since) = since {
+            range.insert("$gt", since.clone());
+        }
+        if let Some(latest) = &latest {
+            range.insert("$lte", latest.clone());
+        }
+
+        let mut filter = selection.filter.clone();
+        if !range.is_empty() {
+            filter = if filter.is_empty() {
+                doc! { field: range }
+            } else {
+                doc! { "$and": [filter, { field: range }] }
+            };
+        }
+        let selection = Selection {
+            filter,
+            limit: None,
+            sample: None,
+        };
+
+        self.copy_collection(spec, &selection, WriteMode::Upsert, bar, transferred)
+            .await?;
+
+        Ok(latest.or_else(|| since.clone()))
+    }
+}
+
+/// `system.*`을 뺀 source db의 collection 이름을 정렬해 돌려줍니다.
+pub async fn collection_names(uri: &str, db_name: &str) -> Vec<String> {
+    let mut names: Vec<String> = connect(uri, db_name)
+        .await
+        .list_collection_names()
+        .await
+        .unwrap_or_else(|e| exit_with_error!("failed to list collections:\n{}", e))
+        .into_iter()
+        .filter(|name| !name.starts_with("system."))
+        .collect();
+
+    names.sort();
+    names
+}
+
+pub async fn run(plan: &DumpPlan) -> Vec<CollectionReport> {
+    let filters: HashMap<&str, Document> = plan
+        .queries
+        .iter()
+        .map(|(col, query)| (col.as_str(), parse_query(col, query)))
+        .collect();
+    let source_db = connect(&plan.source_uri, &plan.source_db).await;
+    let target_db = connect(&plan.target_uri, &plan.target_db).await;
+
+    let specs: Vec<CollectionSpecification> = source_db
+        .list_collections()
+        .await
+        .unwrap_or_else(|e| exit_with_error!("failed to list collections:\n{}", e))
+        .try_collect()
+        .await
+        .unwrap_or_else(|e| exit_with_error!("failed to list collections:\n{}", e));
+
+    // 요청한 collection이 source에 없으면 복사하지 않고 실패로 보고합니다.
+    let mut reports: Vec<CollectionReport> = plan
+        .collections
+        .iter()
+        .flatten()
+        .filter(|col| !specs.iter().any(|spec| &spec.name == *col))
+        .map(|col| CollectionReport {
+            name: col.clone(),
+            documents: 0,
+            bytes: None,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+            error: Some(format!("collection not found in {}", plan.source_db)),
+        })
+        .collect();
+
+    let targets = specs.into_iter().filter(|spec| {
+        !spec.name.starts_with("system.")
+            && match &plan.collections {
+                Some(cols) => cols.contains(&spec.name),
+                None => !plan.excludes.contains(&spec.name),
+            }
+    });
+
+    let copier = Arc::new(Copier {
+        source_db,
+        target_db,
+        masker: Masker::new(&plan.mask_seed, &plan.masking),
+        limiter: RateLimiter::new(&plan.throttle),
+    });
+    let namespace = sync_state::namespace(&plan.service, &plan.target_db);
+    let state = Arc::new(Mutex::new(if plan.incremental {
+        SyncState::load()
+    } else {
+        SyncState::default()
+    }));
+    let mode = if plan.drop {
+        WriteMode::Replace
+    } else {
+        WriteMode::Merge
+    };
+    let multi = MultiProgress::new();
+    let jobs = plan.jobs.max(1);
+    let permits = Arc::new(Semaphore::new(jobs));
+    let started = Arc::new(AtomicUsize::new(0));
+    let pause = throttle::pause(&plan.throttle);
+    let mut copy_tasks = JoinSet::new();
+
+    for spec in targets {
+        let copier = Arc::clone(&copier);
+        let multi = multi.clone();
+        let retries = plan.retries_for(&spec.name);
+        let selection = Selection {
+            filter: filters.get(spec.name.as_str()).cloned().unwrap_or_default(),
+            limit: plan.limit,
+            sample: plan.sample,
+        };
+        let incremental = plan.incremental.then(|| {
+            let field = plan.incremental_field(&spec.name).to_string();
+            let since = state
+                .lock()
+                .expect("sync state poisoned")
+                .get(&namespace, &spec.name, &field)
+                .and_then(Checkpoint::value);
+
+            Incremental { field, since }
+        });
+        let permits = Arc::clone(&permits);
+        let started = Arc::clone(&started);
+        let state = Arc::clone(&state);
+        let namespace = namespace.clone();
+
+        copy_tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await.expect("semaphore closed");
+            // 기다리는 collection까지 bar를 그리면 db 전체를 복사할 때 화면이 넘치므로 시작할 때 만듭니다.
+            let bar = progress::document_bar(&multi, &spec.name, 0);
+            // 처음 jobs개를 뺀 나머지는 앞 collection이 끝난 자리에서 시작하므로 쉬었다 갑니다.
+            if started.fetch_add(1, Ordering::SeqCst) >= jobs && !pause.is_zero() {
+                bar.set_message("pausing");
+                tokio::time::sleep(pause).await;
+            }
+            let started_at = Instant::now();
+            let mut attempts = 1;
+
+            // target collection을 먼저 drop하거나 이미 있는 _id를 건너뛰므로 처음부터 다시 복사해도 안전합니다.
+            loop {
+                let mut transferred = Transferred::default();
+                let copied = match &incremental {
+                    Some(incremental) => copier
+                        .sync_collection(&spec, &selection, incremental, &bar, &mut transferred)
+                        .await
+                        .map(|latest| {
+                            if let Some(latest) = latest {
+                                state.lock().expect("sync state poisoned").set(
+                                    &namespace,
+                                    &spec.name,
+                                    Checkpoint::new(&incremental.field, latest),
+                                );
+                            }
+                        }),
+                    None => {
+                        copier
+                            .copy_collection(&spec, &selection, mode, &bar, &mut transferred)
+                            .await
+                    }
+                };
+
+                if copied.is_ok() || attempts > retries {
+                    progress::finish(&bar, copied.is_ok());
+
+                    return CollectionReport {
+                        name: spec.name,
+                        documents: transferred.documents,
+                        bytes: Some(transferred.bytes),
+                        elapsed: started_at.elapsed(),
+                        attempts,
+                        error: copied.err().map(|e| e.to_string()),
+                    };
+                }
+
+                bar.reset();
+                bar.set_message(format!("retry {}/{}", attempts, retries));
+                attempts += 1;
+            }
+        });
+    }
+
+    while let Some(joined) = copy_tasks.join_next().await {
+        reports.push(joined.expect("copy task panicked"));
+    }
+
+    // 성공한 collection의 동기화 지점만 갱신됩니다.
+    if plan.incremental {
+        state.lock().expect("sync state poisoned").save();
+    }
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::options::IndexOptions;
+
+    /// 지워도 되는 mongod의 uri. 예: `SPRT_TEST_MONGO_URI=mongodb://localhost:27017 cargo test -- --ignored`
+    const TEST_URI_ENV: &str = "SPRT_TEST_MONGO_URI";
+
+    fn test_plan(uri: &str, source_db: &str, target_db: &str, drop: bool) -> DumpPlan {
+        DumpPlan {
+            service: "test".to_string(),
+            source_uri: uri.to_string(),
+            target_uri: uri.to_string(),
+            source_db: source_db.to_string(),
+            target_db: target_db.to_string(),
+            collections: Some(vec!["users".to_string()]),
+            jobs: 1,
+            drop,
+            ..Default::default()
+        }
+    }
+
+    async fn copy(plan: &DumpPlan) {
+        let reports = run(plan).await;
+
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].succeeded(), "{:?}", reports[0].error);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a disposable mongod in SPRT_TEST_MONGO_URI"]
+    async fn copies_collection_with_indexes_and_merges_without_drop() {
+        let uri = std::env::var(TEST_URI_ENV).expect("SPRT_TEST_MONGO_URI not set");
+        let source_db = format!("sprt_test_source_{}", std::process::id());
+        let target_db = format!("sprt_test_target_{}", std::process::id());
+        let client = Client::with_uri_str(&uri).await.unwrap();
+        let source = client.database(&source_db).collection::<Document>("users");
+        let target = client.database(&target_db).collection::<Document>("users");
+
+        source
+            .insert_many([
+                doc! { "_id": 1, "email": "a@example.com" },
+                doc! { "_id": 2, "email": "b@example.com" },
+                doc! { "_id": 3, "email": "c@example.com" },
+            ])
+            .await
+            .unwrap();
+        source
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "email": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("email_1".to_string())
+                            .unique(true)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await
+            .unwrap();
+
+        // drop하고 복사하면 document와 index가 source와 같아집니다.
+        copy(&test_plan(&uri, &source_db, &target_db, true)).await;
+        assert_eq!(target.count_documents(doc! {}).await.unwrap(), 3);
+        assert!(target
+            .list_index_names()
+            .await
+            .unwrap()
+            .contains(&"email_1".to_string()));
+
+        // --no-drop은 target에 이미 있는 _id를 그대로 두고 새 document만 더합니다.
+        target
+            .update_one(
+                doc! { "_id": 1 },
+                doc! { "$set": { "email": "changed@example.com" } },
+            )
+            .await
+            .unwrap();
+        source
+            .insert_one(doc! { "_id": 4, "email": "d@example.com" })
+            .await
+            .unwrap();
+        copy(&test_plan(&uri, &source_db, &target_db, false)).await;
+        assert_eq!(target.count_documents(doc! {}).await.unwrap(), 4);
+        assert_eq!(
+            target.find_one(doc! { "_id": 1 }).await.unwrap().unwrap(),
+            doc! { "_id": 1, "email": "changed@example.com" }
+        );
+
+        // upsert(--incremental)는 같은 _id를 source의 document로 덮어씁니다.
+        let raw_target = client
+            .database(&target_db)
+            .collection::<RawDocumentBuf>("users");
+        let mut batch =
+            vec![
+                RawDocumentBuf::from_document(&doc! { "_id": 1, "email": "a@example.com" })
+                    .unwrap(),
+            ];
+        assert_eq!(
+            flush(&raw_target, &mut batch, WriteMode::Upsert)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(target.count_documents(doc! {}).await.unwrap(), 4);
+        assert_eq!(
+            target.find_one(doc! { "_id": 1 }).await.unwrap().unwrap(),
+            doc! { "_id": 1, "email": "a@example.com" }
+        );
+
+        client.database(&source_db).drop().await.unwrap();
+        client.database(&target_db).drop().await.unwrap();
+    }
+}
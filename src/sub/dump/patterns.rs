@@ -0,0 +1,160 @@
+use crate::exit_with_error;
+use regex::Regex;
+
+/// config의 `includes`/`excludes`에 적는 collection 이름.
+/// `rounds`처럼 그대로 쓰거나, `points_users_*` 같은 glob, `/.*_logs$/` 같은 정규식을 쓸 수 있습니다.
+pub enum CollectionPattern {
+    Exact(String),
+    Pattern { source: String, regex: Regex },
+}
+
+impl CollectionPattern {
+    pub fn parse(pattern: &str) -> CollectionPattern {
+        let regex = if let Some(regex) = pattern
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            regex.to_string()
+        } else if pattern.contains(['*', '?']) {
+            format!(
+                "^{}$",
+                regex::escape(pattern)
+                    .replace(r"\*", ".*")
+                    .replace(r"\?", ".")
+            )
+        } else {
+            return CollectionPattern::Exact(pattern.to_string());
+        };
+
+        match Regex::new(&regex) {
+            Ok(regex) => CollectionPattern::Pattern {
+                source: pattern.to_string(),
+                regex,
+            },
+            Err(e) => exit_with_error!("invalid collection pattern {}:\n{}", pattern, e),
+        }
+    }
+
+    pub fn matches(&self, collection: &str) -> bool {
+        match self {
+            CollectionPattern::Exact(name) => name == collection,
+            CollectionPattern::Pattern { regex, .. } => regex.is_match(collection),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        match self {
+            CollectionPattern::Exact(name) => name,
+            CollectionPattern::Pattern { source, .. } => source,
+        }
+    }
+}
+
+/// `includes`가 비어 있으면 모든 collection이 포함되고, `excludes`가 항상 우선합니다.
+pub struct CollectionFilter {
+    includes: Vec<CollectionPattern>,
+    excludes: Vec<CollectionPattern>,
+}
+
+/// collection 하나가 filter를 통과했는지, 아니면 왜 빠졌는지
+pub enum Verdict<'a> {
+    Selected,
+    NotIncluded,
+    ExcludedBy(&'a str),
+}
+
+impl CollectionFilter {
+    pub fn new(includes: &[String], excludes: &[String]) -> CollectionFilter {
+        CollectionFilter {
+            includes: includes
+                .iter()
+                .map(|p| CollectionPattern::parse(p))
+                .collect(),
+            excludes: excludes
+                .iter()
+                .map(|p| CollectionPattern::parse(p))
+                .collect(),
+        }
+    }
+
+    pub fn has_includes(&self) -> bool {
+        !self.includes.is_empty()
+    }
+
+    /// 이름을 그대로 적은 것만 있으면 실제 collection 목록 없이도 mongodump에 넘길 수 있습니다.
+    pub fn needs_live_list(&self) -> bool {
+        self.has_includes()
+            || self
+                .excludes
+                .iter()
+                .any(|pattern| matches!(pattern, CollectionPattern::Pattern { .. }))
+    }
+
+    pub fn verdict(&self, collection: &str) -> Verdict<'_> {
+        if let Some(pattern) = self.excludes.iter().find(|p| p.matches(collection)) {
+            return Verdict::ExcludedBy(pattern.source());
+        }
+        if self.has_includes() && !self.includes.iter().any(|p| p.matches(collection)) {
+            return Verdict::NotIncluded;
+        }
+
+        Verdict::Selected
+    }
+
+    pub fn is_excluded(&self, collection: &str) -> bool {
+        matches!(self.verdict(collection), Verdict::ExcludedBy(_))
+    }
+
+    pub fn selects(&self, collection: &str) -> bool {
+        matches!(self.verdict(collection), Verdict::Selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_exact_names() {
+        let pattern = CollectionPattern::parse("rounds");
+
+        assert!(matches!(pattern, CollectionPattern::Exact(_)));
+        assert!(pattern.matches("rounds"));
+        assert!(!pattern.matches("rounds_lectures"));
+        assert_eq!(pattern.source(), "rounds");
+    }
+
+    #[test]
+    fn parses_globs_as_anchored_patterns() {
+        let star = CollectionPattern::parse("points_users*");
+        let question = CollectionPattern::parse("log_?");
+
+        assert!(star.matches("points_users"));
+        assert!(star.matches("points_users_2024"));
+        assert!(!star.matches("old_points_users"));
+        assert!(question.matches("log_a"));
+        assert!(!question.matches("log_ab"));
+        // glob이 아닌 정규식 문자는 그대로 비교합니다.
+        assert!(CollectionPattern::parse("a.b*").matches("a.bc"));
+        assert!(!CollectionPattern::parse("a.b*").matches("axbc"));
+    }
+
+    #[test]
+    fn parses_slashes_as_regex() {
+        let pattern = CollectionPattern::parse("/.*_logs?$/");
+
+        assert!(pattern.matches("access_log"));
+        assert!(pattern.matches("error_logs"));
+        assert!(!pattern.matches("logs_archive"));
+        assert_eq!(pattern.source(), "/.*_logs?$/");
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let filter = CollectionFilter::new(&["rounds*".to_string()], &["rounds_logs".to_string()]);
+
+        assert!(filter.selects("rounds"));
+        assert!(filter.is_excluded("rounds_logs"));
+        assert!(matches!(filter.verdict("users"), Verdict::NotIncluded));
+    }
+}
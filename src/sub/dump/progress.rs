@@ -0,0 +1,62 @@
+use colored::Colorize;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::time::Duration;
+const TICK_INTERVAL: Duration = Duration::from_millis(120);
+
+/// document 개수를 미리 알 수 있는 경우(native engine) 진행률 bar를 만듭니다.
+pub fn document_bar(multi: &MultiProgress, label: &str, total_documents: u64) -> ProgressBar {
+    let bar = multi.add(ProgressBar::new(total_documents));
+
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {prefix:<32} [{bar:25.cyan/blue}] {human_pos}/{human_len} docs {msg} {elapsed}",
+        )
+        .expect("invalid progress template")
+        .progress_chars("=> "),
+    );
+    bar.set_prefix(label.to_string());
+    bar.enable_steady_tick(TICK_INTERVAL);
+
+    bar
+}
+
+/// 전체 크기를 알 수 없는 archive stream(tools engine)의 전송량을 보여주는 bar를 만듭니다.
+pub fn stream_bar(multi: &MultiProgress, label: &str) -> ProgressBar {
+    let bar = multi.add(ProgressBar::no_length());
+
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {prefix:<32} {bytes:>10} {binary_bytes_per_sec:>12} {elapsed} {wide_msg}",
+        )
+        .expect("invalid progress template"),
+    );
+    bar.set_prefix(label.to_string());
+    bar.enable_steady_tick(TICK_INTERVAL);
+
+    bar
+}
+
+/// 크기를 아는 파일을 읽어 보낼 때 쓰는 bar
+pub fn bytes_bar(multi: &MultiProgress, label: &str, total_bytes: u64) -> ProgressBar {
+    let bar = multi.add(ProgressBar::new(total_bytes));
+
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {prefix:<32} [{bar:25.cyan/blue}] {bytes}/{total_bytes} {elapsed} {wide_msg}",
+        )
+        .expect("invalid progress template")
+        .progress_chars("=> "),
+    );
+    bar.set_prefix(label.to_string());
+    bar.enable_steady_tick(TICK_INTERVAL);
+
+    bar
+}
+
+pub fn finish(bar: &ProgressBar, succeeded: bool) {
+    if succeeded {
+        bar.finish_with_message("done".green().to_string());
+    } else {
+        bar.finish_with_message("failed".red().to_string());
+    }
+}
@@ -0,0 +1,127 @@
+use colored::Colorize;
+use std::time::Duration;
+
+/// collection 하나를 복사한 결과
+pub struct CollectionReport {
+    pub name: String,
+    pub documents: u64,
+    /// collection 단위로 전송량을 알 수 없는 경우(db 전체를 하나의 archive로 복사) `None`
+    pub bytes: Option<u64>,
+    pub elapsed: Duration,
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+impl CollectionReport {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+pub fn print_summary(reports: &[CollectionReport], elapsed: Duration) {
+    let header = [
+        "collection",
+        "documents",
+        "bytes",
+        "elapsed",
+        "attempts",
+        "status",
+    ];
+    let rows: Vec<[String; 6]> = reports
+        .iter()
+        .map(|report| {
+            [
+                report.name.clone(),
+                report.documents.to_string(),
+                report.bytes.map_or("-".to_string(), format_bytes),
+                format!("{:.1?}", report.elapsed),
+                report.attempts.to_string(),
+                if report.succeeded() { "ok" } else { "failed" }.to_string(),
+            ]
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|col| {
+            rows.iter()
+                .map(|row| row[col].chars().count())
+                .chain(std::iter::once(header[col].len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    // 색을 입히면 escape 문자 때문에 폭 계산이 틀어지므로 padding을 먼저 합니다.
+    let header_line = header
+        .iter()
+        .zip(&widths)
+        .map(|(title, width)| format!("{:<width$}", title, width = width))
+        .collect::<Vec<String>>()
+        .join("  ");
+
+    println!();
+    println!("{}", header_line.bold());
+    println!("{}", "-".repeat(header_line.chars().count()));
+
+    for (report, row) in reports.iter().zip(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(col, (cell, width))| match col {
+                0 | 5 => format!("{:<width$}", cell, width = width),
+                _ => format!("{:>width$}", cell, width = width),
+            })
+            .collect();
+        let status = if report.succeeded() {
+            cells[5].green()
+        } else {
+            cells[5].red()
+        };
+
+        println!("{}  {}", cells[..5].join("  "), status);
+    }
+
+    let failed = reports.iter().filter(|report| !report.succeeded()).count();
+    let documents: u64 = reports.iter().map(|report| report.documents).sum();
+    let total = format!(
+        "{} collection(s), {} document(s) in {:.1?}",
+        reports.len(),
+        documents,
+        elapsed
+    );
+
+    println!();
+    if failed == 0 {
+        println!("{} {}", "✔".green(), total);
+    } else {
+        println!("{} {}, {} failed", "✘".red(), total, failed);
+    }
+
+    for report in reports.iter().filter(|report| !report.succeeded()) {
+        eprintln!(
+            "{} {} (after {} attempt(s)): {}",
+            "✘".red(),
+            report.name,
+            report.attempts,
+            report.error.as_deref().unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use super::history::{self, HistoryEntry, TRIGGER_ENV};
+use crate::config::DumpSchedule;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, Timelike};
+use colored::Colorize;
+use std::{process::Command, time::Instant};
+// 1년 안에 한 번도 맞지 않는 표현식(예: 2월 30일)은 실행하지 않습니다.
+const SEARCH_DAYS: i64 = 366;
+
+/// `분 시 일 월 요일` 다섯 field의 cron 표현식. `*`, `1-5`, `*/15`, `1,15`, `9-18/3`을 쓸 수 있고
+/// 요일은 0(일)~6(토), 7도 일요일입니다. 일과 요일을 모두 제한하면 cron처럼 둘 중 하나만 맞아도 실행합니다.
+pub struct CronExpr {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+fn parse_number(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|number| (min..=max).contains(number))
+        .ok_or_else(|| format!("{} is not between {} and {}", value, min, max))
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max as usize + 1];
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_number(step, 1, max)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => {
+                    (parse_number(start, min, max)?, parse_number(end, min, max)?)
+                }
+                // `5/10`은 5부터 끝까지 10마다
+                None if part.contains('/') => (parse_number(range, min, max)?, max),
+                None => {
+                    let value = parse_number(range, min, max)?;
+
+                    (value, value)
+                }
+            },
+        };
+
+        if start > end {
+            return Err(format!("{} is not a valid range", range));
+        }
+        (start..=end)
+            .step_by(step as usize)
+            .for_each(|value| allowed[value as usize] = true);
+    }
+
+    Ok(allowed)
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<CronExpr, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "{} needs 5 fields (minute hour day month weekday)",
+                expr
+            ));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+
+        weekdays[0] |= weekdays[7];
+        Ok(CronExpr {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            // cron처럼 `*`로 시작하는 field(`*/2` 포함)는 제한하지 않은 것으로 봅니다.
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let day = self.days[date.day() as usize];
+        let weekday = self.weekdays[date.weekday().num_days_from_sunday() as usize];
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        };
+
+        self.months[date.month() as usize] && day_matches
+    }
+
+    /// `after`보다 뒤에 오는 첫 실행 시각. 일광 절약 시간 때문에 없는 시각은 건너뜁니다.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = (after.naive_local() + Duration::minutes(1))
+            .with_second(0)
+            .and_then(|start| start.with_nanosecond(0))?;
+
+        (0..=SEARCH_DAYS)
+            .map(|offset| start.date() + Duration::days(offset))
+            .filter(|date| self.matches_date(*date))
+            .flat_map(|date| {
+                (0..24u32)
+                    .filter(|hour| self.hours[*hour as usize])
+                    .flat_map(move |hour| {
+                        (0..60u32)
+                            .filter(|minute| self.minutes[*minute as usize])
+                            .filter_map(move |minute| {
+                                NaiveTime::from_hms_opt(hour, minute, 0)
+                                    .map(|time| date.and_time(time))
+                            })
+                    })
+            })
+            .filter(|time| *time >= start)
+            .find_map(|time| time.and_local_timezone(Local).earliest())
+    }
+}
+
+/// 실행할 dump 하나: service와 `sprt dump`에 넘길 나머지 인자
+pub struct ScheduledDump {
+    pub service: String,
+    pub schedule: DumpSchedule,
+    pub cron: CronExpr,
+}
+
+impl ScheduledDump {
+    /// 데몬은 사람이 답할 수 없으므로 항상 `--yes`를 붙입니다.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["dump".to_string(), "-s".to_string(), self.service.clone()];
+
+        args.extend(self.schedule.args.iter().cloned());
+        if !args.iter().any(|arg| arg == "--yes" || arg == "-y") {
+            args.push("--yes".to_string());
+        }
+        args
+    }
+
+    pub fn describe(&self) -> String {
+        format!("[{}] sprt {}", self.schedule.cron, self.args().join(" "))
+    }
+}
+
+/// 같은 시각에 걸린 dump는 config 순서대로 하나씩 실행합니다.
+/// 실행이 길어져 지나간 시각은 따라잡지 않고 다음 시각을 기다립니다.
+pub async fn run(dumps: &[ScheduledDump]) {
+    let exe = std::env::current_exe().expect("failed to get current executable");
+
+    loop {
+        let now = Local::now();
+        let Some(next) = dumps
+            .iter()
+            .filter_map(|dump| dump.cron.next_after(now))
+            .min()
+        else {
+            eprintln!("{} no schedule will run within a year", "✘".red());
+            return;
+        };
+
+        println!("next run at {}", next.format("%Y-%m-%d %H:%M"));
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        for dump in dumps
+            .iter()
+            .filter(|dump| dump.cron.next_after(now) == Some(next))
+        {
+            println!(
+                "{} {} {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                "▶".cyan(),
+                dump.describe()
+            );
+            let trigger = format!("schedule:{}", dump.schedule.cron);
+            let recorded_failures = history::failed_count(&trigger);
+            let (started_at, timer) = (Local::now(), Instant::now());
+            let status = Command::new(&exe)
+                .args(dump.args())
+                .env(TRIGGER_ENV, &trigger)
+                .status();
+            let error = match status {
+                Ok(status) if status.success() => {
+                    println!("{} {}", "✔".green(), dump.service);
+                    continue;
+                }
+                Ok(status) => format!("{} exited with {}", dump.service, status),
+                Err(e) => format!("failed to start {}: {}", dump.service, e),
+            };
+
+            eprintln!("{} {}", "✘".red(), error);
+            // guard 거절이나 연결 실패처럼 engine을 돌리기 전에 끝나면 dump가 기록을 남기지 않으므로 대신 남깁니다.
+            if history::failed_count(&trigger) == recorded_failures {
+                history::append(&HistoryEntry::failed_run(
+                    &dump.service,
+                    &trigger,
+                    started_at,
+                    timer.elapsed(),
+                    error,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .single()
+            .unwrap()
+    }
+
+    fn next(expr: &str, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        CronExpr::parse(expr).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn follows_ranges_and_lists() {
+        assert_eq!(
+            next("0 9-17 * * *", at(2024, 1, 1, 17, 30)),
+            Some(at(2024, 1, 2, 9, 0))
+        );
+        assert_eq!(
+            next("0,30 9 * * *", at(2024, 1, 1, 9, 0)),
+            Some(at(2024, 1, 1, 9, 30))
+        );
+        // 2024-01-06은 토요일
+        assert_eq!(
+            next("0 7 * * 1-5", at(2024, 1, 5, 8, 0)),
+            Some(at(2024, 1, 8, 7, 0))
+        );
+    }
+
+    #[test]
+    fn follows_steps() {
+        assert_eq!(
+            next("*/15 * * * *", at(2024, 1, 1, 10, 7)),
+            Some(at(2024, 1, 1, 10, 15))
+        );
+        assert_eq!(
+            next("0 9-18/3 * * *", at(2024, 1, 1, 12, 0)),
+            Some(at(2024, 1, 1, 15, 0))
+        );
+        assert_eq!(
+            next("0 5/10 * * *", at(2024, 1, 1, 6, 0)),
+            Some(at(2024, 1, 1, 15, 0))
+        );
+    }
+
+    #[test]
+    fn treats_weekday_7_as_sunday() {
+        // 2024-01-01은 월요일
+        assert_eq!(
+            next("0 0 * * 7", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 7, 0, 0))
+        );
+        assert_eq!(
+            next("0 0 * * 0", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 7, 0, 0))
+        );
+    }
+
+    #[test]
+    fn matches_either_day_or_weekday_when_both_are_restricted() {
+        // 13일 또는 금요일: 2024-01-05가 첫 금요일
+        assert_eq!(
+            next("0 0 13 * 5", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 5, 0, 0))
+        );
+        // `*/2`는 제한하지 않은 것으로 보므로 월요일에만 실행합니다.
+        assert_eq!(
+            next("0 0 */2 * 1", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 8, 0, 0))
+        );
+    }
+
+    #[test]
+    fn never_matches_impossible_dates() {
+        assert_eq!(next("0 0 30 2 *", at(2024, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(CronExpr::parse("* * * *").is_err());
+        assert!(CronExpr::parse("60 * * * *").is_err());
+        assert!(CronExpr::parse("5-1 * * * *").is_err());
+        assert!(CronExpr::parse("*/0 * * * *").is_err());
+        assert!(CronExpr::parse("0 0 * * 8").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+use super::native;
+use colored::Colorize;
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Database,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// field 경로 → 그 경로에서 본 type들. 배열 원소는 `tags[]`처럼 적습니다.
+type Schema = BTreeMap<String, BTreeSet<&'static str>>;
+
+#[derive(Serialize)]
+pub struct TypeChange {
+    pub field: String,
+    pub source: BTreeSet<&'static str>,
+    pub target: BTreeSet<&'static str>,
+}
+
+/// collection 하나의 schema 차이. `only_in_source`는 target을 만든 뒤 source에 새로 생긴 field입니다.
+#[derive(Serialize)]
+pub struct CollectionDrift {
+    pub name: String,
+    pub only_in_source: Vec<String>,
+    pub only_in_target: Vec<String>,
+    pub type_changes: Vec<TypeChange>,
+}
+
+impl CollectionDrift {
+    fn is_empty(&self) -> bool {
+        self.only_in_source.is_empty()
+            && self.only_in_target.is_empty()
+            && self.type_changes.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+pub struct SchemaDiff {
+    pub source_db: String,
+    pub target_db: String,
+    pub sample: u64,
+    pub collections_only_in_source: Vec<String>,
+    pub collections_only_in_target: Vec<String>,
+    pub collections: Vec<CollectionDrift>,
+}
+
+fn type_name(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Array(_) => "array",
+        Bson::Document(_) => "object",
+        Bson::Boolean(_) => "bool",
+        Bson::Null => "null",
+        Bson::RegularExpression(_) => "regex",
+        Bson::JavaScriptCode(_) | Bson::JavaScriptCodeWithScope(_) => "javascript",
+        Bson::Int32(_) => "int",
+        Bson::Int64(_) => "long",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::Binary(_) => "binData",
+        Bson::ObjectId(_) => "objectId",
+        Bson::DateTime(_) => "date",
+        Bson::Symbol(_) => "symbol",
+        Bson::Decimal128(_) => "decimal",
+        Bson::Undefined => "undefined",
+        Bson::MaxKey => "maxKey",
+        Bson::MinKey => "minKey",
+        Bson::DbPointer(_) => "dbPointer",
+    }
+}
+
+fn collect_fields(prefix: &str, value: &Bson, schema: &mut Schema) {
+    schema
+        .entry(prefix.to_string())
+        .or_default()
+        .insert(type_name(value));
+
+    match value {
+        Bson::Document(document) => document
+            .iter()
+            .for_each(|(key, value)| collect_fields(&format!("{}.{}", prefix, key), value, schema)),
+        Bson::Array(items) => items
+            .iter()
+            .for_each(|item| collect_fields(&format!("{}[]", prefix), item, schema)),
+        _ => {}
+    }
+}
+
+fn infer(documents: &[Document]) -> Schema {
+    let mut schema = Schema::new();
+
+    for document in documents {
+        for (key, value) in document {
+            collect_fields(key, value, &mut schema);
+        }
+    }
+    schema
+}
+
+async fn sample_schema(db: &Database, name: &str, size: u64) -> mongodb::error::Result<Schema> {
+    let documents: Vec<Document> = db
+        .collection::<Document>(name)
+        .aggregate(vec![doc! { "$sample": { "size": size as i64 } }])
+        .await?
+        .try_collect()
+        .await?;
+
+    Ok(infer(&documents))
+}
+
+fn compare(name: &str, source: &Schema, target: &Schema) -> CollectionDrift {
+    CollectionDrift {
+        name: name.to_string(),
+        only_in_source: source
+            .keys()
+            .filter(|field| !target.contains_key(*field))
+            .cloned()
+            .collect(),
+        only_in_target: target
+            .keys()
+            .filter(|field| !source.contains_key(*field))
+            .cloned()
+            .collect(),
+        type_changes: source
+            .iter()
+            .filter_map(|(field, source_types)| {
+                let target_types = target.get(field)?;
+
+                (source_types != target_types).then(|| TypeChange {
+                    field: field.clone(),
+                    source: source_types.clone(),
+                    target: target_types.clone(),
+                })
+            })
+            .collect(),
+    }
+}
+
+/// `collections`는 source 기준으로 비교할 collection, `selects`는 target 쪽 collection을 같은 규칙으로 거르는 함수입니다.
+pub async fn diff(
+    source_uri: &str,
+    target_uri: &str,
+    source_db: &str,
+    target_db: &str,
+    collections: &[String],
+    selects: &dyn Fn(&str) -> bool,
+    sample: u64,
+) -> mongodb::error::Result<SchemaDiff> {
+    let source = native::connect(source_uri, source_db).await;
+    let target = native::connect(target_uri, target_db).await;
+    let target_collections: Vec<String> = native::collection_names(target_uri, target_db)
+        .await
+        .into_iter()
+        .filter(|col| selects(col))
+        .collect();
+    let mut diff = SchemaDiff {
+        source_db: source_db.to_string(),
+        target_db: target_db.to_string(),
+        sample,
+        collections_only_in_source: vec![],
+        collections_only_in_target: target_collections
+            .iter()
+            .filter(|col| !collections.contains(col))
+            .cloned()
+            .collect(),
+        collections: vec![],
+    };
+
+    for name in collections {
+        if !target_collections.contains(name) {
+            diff.collections_only_in_source.push(name.clone());
+            continue;
+        }
+
+        let drift = compare(
+            name,
+            &sample_schema(&source, name, sample).await?,
+            &sample_schema(&target, name, sample).await?,
+        );
+        if !drift.is_empty() {
+            diff.collections.push(drift);
+        }
+    }
+
+    Ok(diff)
+}
+
+fn join(types: &BTreeSet<&'static str>) -> String {
+    types.iter().copied().collect::<Vec<&str>>().join("|")
+}
+
+pub fn print_text(diff: &SchemaDiff) {
+    println!(
+        "schema diff {}.* -> {}.* (sampled {} document(s) per collection)",
+        diff.source_db, diff.target_db, diff.sample
+    );
+
+    if diff.collections_only_in_source.is_empty()
+        && diff.collections_only_in_target.is_empty()
+        && diff.collections.is_empty()
+    {
+        println!("  {} no drift found", "✔".green());
+        return;
+    }
+
+    diff.collections_only_in_source
+        .iter()
+        .for_each(|col| println!("  {} {} (only in source)", "+".green(), col));
+    diff.collections_only_in_target
+        .iter()
+        .for_each(|col| println!("  {} {} (only in target)", "-".red(), col));
+
+    for drift in &diff.collections {
+        println!("  {} {}", "~".yellow(), drift.name.bold());
+        drift
+            .only_in_source
+            .iter()
+            .for_each(|field| println!("      {} {}", "+".green(), field));
+        drift
+            .only_in_target
+            .iter()
+            .for_each(|field| println!("      {} {}", "-".red(), field));
+        drift.type_changes.iter().for_each(|change| {
+            println!(
+                "      {} {}: {} -> {}",
+                "~".yellow(),
+                change.field,
+                join(&change.target),
+                join(&change.source)
+            )
+        });
+    }
+}
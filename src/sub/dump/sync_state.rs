@@ -0,0 +1,93 @@
+use crate::exit_with_error;
+use chrono::Local;
+use mongodb::bson::Bson;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, create_dir_all},
+    path::PathBuf,
+};
+const SYNC_STATE_SUFFIX: &str = "sprt/sync_state.json";
+
+/// collection 하나를 마지막으로 동기화한 지점
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub field: String,
+    /// 마지막으로 복사한 field 값(canonical extended JSON)
+    pub value: serde_json::Value,
+    pub synced_at: String,
+}
+
+impl Checkpoint {
+    pub fn new(field: &str, value: Bson) -> Checkpoint {
+        Checkpoint {
+            field: field.to_string(),
+            value: value.into_canonical_extjson(),
+            synced_at: Local::now().to_rfc3339(),
+        }
+    }
+
+    pub fn value(&self) -> Option<Bson> {
+        Bson::try_from(self.value.clone()).ok()
+    }
+}
+
+/// `--incremental` 실행 사이에 유지하는 동기화 지점.
+/// `{service}:{target_db}` → collection 이름 → checkpoint 형태로 `~/sprt/sync_state.json`에 저장합니다.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncState {
+    #[serde(flatten)]
+    namespaces: HashMap<String, HashMap<String, Checkpoint>>,
+}
+
+fn state_path() -> PathBuf {
+    dirs::home_dir()
+        .expect("failed to get home dir")
+        .join(SYNC_STATE_SUFFIX)
+}
+
+pub fn namespace(service: &str, target_db: &str) -> String {
+    format!("{}:{}", service, target_db)
+}
+
+impl SyncState {
+    pub fn load() -> SyncState {
+        let path = state_path();
+
+        if !path.exists() {
+            return SyncState::default();
+        }
+
+        let content = fs::read_to_string(&path)
+            .unwrap_or_else(|e| exit_with_error!("failed to read {}:\n{}", path.display(), e));
+
+        serde_json::from_str(&content)
+            .unwrap_or_else(|e| exit_with_error!("failed to parse {}:\n{}", path.display(), e))
+    }
+
+    pub fn save(&self) {
+        let path = state_path();
+        let parent = path.parent().expect("failed to get parent dir path");
+
+        create_dir_all(parent).expect("failed to create dir");
+        let content = serde_json::to_string_pretty(self).expect("failed to serialize sync state");
+
+        fs::write(&path, content)
+            .unwrap_or_else(|e| exit_with_error!("failed to write {}:\n{}", path.display(), e));
+    }
+
+    /// 기록할 때와 다른 field로 동기화하면 이전 checkpoint는 쓰지 않습니다.
+    pub fn get(&self, namespace: &str, collection: &str, field: &str) -> Option<&Checkpoint> {
+        self.namespaces
+            .get(namespace)
+            .and_then(|collections| collections.get(collection))
+            .filter(|checkpoint| checkpoint.field == field)
+    }
+
+    pub fn set(&mut self, namespace: &str, collection: &str, checkpoint: Checkpoint) {
+        self.namespaces
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(collection.to_string(), checkpoint);
+    }
+}
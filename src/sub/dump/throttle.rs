@@ -0,0 +1,84 @@
+use crate::config::Throttle;
+use std::{
+    io::{self, Read},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+const MB: f64 = 1024.0 * 1024.0;
+
+/// 동시에 도는 모든 collection이 함께 쓰는 속도 제한.
+/// 시작한 뒤 읽은 총량이 제한 속도로 읽었을 때보다 앞서면 그만큼 기다리게 합니다.
+pub struct RateLimiter {
+    docs_per_sec: Option<u64>,
+    bytes_per_sec: Option<f64>,
+    started_at: Instant,
+    /// 지금까지 읽은 (document 수, byte 수)
+    consumed: Mutex<(u64, u64)>,
+}
+
+impl RateLimiter {
+    /// 속도 제한이 없으면 `None`
+    pub fn new(throttle: &Throttle) -> Option<Arc<RateLimiter>> {
+        (throttle.docs_per_sec.is_some() || throttle.mb_per_sec.is_some()).then(|| {
+            Arc::new(RateLimiter {
+                docs_per_sec: throttle.docs_per_sec,
+                bytes_per_sec: throttle.mb_per_sec.map(|mb| mb * MB),
+                started_at: Instant::now(),
+                consumed: Mutex::new((0, 0)),
+            })
+        })
+    }
+
+    /// 방금 읽은 양을 더하고, 제한 속도를 지키려면 얼마나 기다려야 하는지 돌려줍니다.
+    pub fn delay(&self, documents: u64, bytes: u64) -> Duration {
+        let mut consumed = self.consumed.lock().expect("rate limiter poisoned");
+
+        consumed.0 += documents;
+        consumed.1 += bytes;
+
+        let by_documents = self
+            .docs_per_sec
+            .map_or(0.0, |limit| consumed.0 as f64 / limit as f64);
+        let by_bytes = self
+            .bytes_per_sec
+            .map_or(0.0, |limit| consumed.1 as f64 / limit);
+        let expected = Duration::from_secs_f64(by_documents.max(by_bytes));
+
+        expected.saturating_sub(self.started_at.elapsed())
+    }
+
+    /// 한 번에 몰아 읽지 않도록 batch 하나를 1초 분량의 document로 줄입니다.
+    pub fn batch_documents(&self, default: usize) -> usize {
+        self.docs_per_sec
+            .map_or(default, |limit| default.min(limit as usize).max(1))
+    }
+}
+
+/// 읽은 byte만큼 `RateLimiter`에 맞춰 쉬는 reader. mongodump archive를 천천히 받아
+/// pipe가 차면 mongodump도 source를 천천히 읽게 됩니다.
+pub struct ThrottledReader<R> {
+    inner: R,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<R: Read> ThrottledReader<R> {
+    pub fn new(inner: R, limiter: Option<Arc<RateLimiter>>) -> ThrottledReader<R> {
+        ThrottledReader { inner, limiter }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if let Some(limiter) = &self.limiter {
+            thread::sleep(limiter.delay(0, read as u64));
+        }
+        Ok(read)
+    }
+}
+
+pub fn pause(throttle: &Throttle) -> Duration {
+    Duration::from_millis(throttle.pause_ms.unwrap_or_default())
+}
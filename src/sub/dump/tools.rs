@@ -0,0 +1,670 @@
+use super::{
+    mask_uri, progress,
+    report::CollectionReport,
+    throttle::{self, RateLimiter, ThrottledReader},
+    DumpPlan,
+};
+use crate::exit_with_error;
+use indicatif::{MultiProgress, ProgressBar};
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+    process::{self, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+const MONGO_DUMP: &str = "mongodump";
+const MONGO_RESTORE: &str = "mongorestore";
+const DUPLICATE_KEY_ERROR: &str = "E11000 duplicate key error collection: ";
+
+fn base_dump_command(plan: &DumpPlan) -> Command {
+    let mut dump_command = Command::new(MONGO_DUMP);
+
+    dump_command
+        .arg("--archive")
+        .arg("--gzip")
+        .arg(format!("--uri={}", plan.source_uri))
+        .arg(format!("--db={}", plan.source_db));
+
+    dump_command
+}
+
+fn dump_command(plan: &DumpPlan, collection: Option<&str>) -> Command {
+    let mut dump_command = base_dump_command(plan);
+    let with_default_options = &mut dump_command;
+
+    match collection {
+        Some(collection) => {
+            with_default_options.arg(format!("--collection={}", collection));
+
+            if plan.queries.contains_key(collection) {
+                with_default_options.arg(format!(
+                    "--queryFile={}",
+                    query_file(plan, collection).display()
+                ));
+            }
+        }
+        None => {
+            plan.excludes
+                .iter()
+                .fold(with_default_options, |base, exclude| {
+                    base.arg(format!("--excludeCollection={}", exclude))
+                });
+        }
+    }
+
+    dump_command
+}
+
+/// link로 채운 `$in` query는 argv 하나의 길이 제한(128KB)을 넘을 수 있으므로
+/// command line 대신 `--queryFile`로 넘깁니다. target db마다 process의 임시 디렉토리 아래에 둡니다.
+fn query_dir(plan: &DumpPlan) -> PathBuf {
+    env::temp_dir().join(format!("sprt-{}-{}", process::id(), plan.target_db))
+}
+
+fn query_file(plan: &DumpPlan, collection: &str) -> PathBuf {
+    query_dir(plan).join(format!("{}.json", collection))
+}
+
+/// mongodump를 띄우기 전에 plan의 query를 파일로 쓰고, drop될 때 지웁니다.
+struct QueryFiles<'a>(&'a DumpPlan);
+
+impl<'a> QueryFiles<'a> {
+    fn write(plan: &'a DumpPlan) -> Result<Self, String> {
+        let query_files = QueryFiles(plan);
+
+        if !plan.queries.is_empty() {
+            let dir = query_dir(plan);
+
+            fs::create_dir_all(&dir)
+                .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+            plan.queries.iter().try_for_each(|(collection, query)| {
+                let path = query_file(plan, collection);
+
+                fs::write(&path, query)
+                    .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+            })?;
+        }
+
+        Ok(query_files)
+    }
+}
+
+impl Drop for QueryFiles<'_> {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(query_dir(self.0));
+    }
+}
+
+fn restore_command(plan: &DumpPlan) -> Command {
+    let mut restore_command = Command::new(MONGO_RESTORE);
+
+    if plan.drop {
+        restore_command.arg("--drop");
+    }
+    restore_command
+        .arg(format!("--uri={}", plan.target_uri))
+        .arg(format!("--nsFrom={}.*", plan.source_db))
+        .arg(format!("--nsTo={}.*", plan.target_db))
+        .arg("--gzip")
+        .arg("--archive");
+
+    restore_command
+}
+
+/// archive 파일 하나에 담을 mongodump. mongodump는 collection을 하나만 고를 수 있으므로
+/// 여러 collection은 `run_dump`에서 나머지 collection을 `excludes`로 바꿔 db 전체로 넘깁니다.
+fn archive_dump_command(plan: &DumpPlan) -> Command {
+    match plan.collections.as_deref() {
+        Some([collection]) => dump_command(plan, Some(collection)),
+        _ => dump_command(plan, None),
+    }
+}
+
+/// collection마다 pipeline을 하나씩 만들고, db 전체를 복사할 때는 `None` 하나만 돌려줍니다.
+fn pipeline_targets(plan: &DumpPlan) -> Vec<Option<String>> {
+    match &plan.collections {
+        Some(cols) => cols.iter().cloned().map(Some).collect(),
+        None => vec![None],
+    }
+}
+
+pub fn display_command(command: &Command) -> String {
+    std::iter::once(command.get_program().to_string_lossy().to_string())
+        .chain(
+            command
+                .get_args()
+                .map(|arg| mask_uri(&arg.to_string_lossy())),
+        )
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn print_archive_commands(plan: &DumpPlan, to_file: Option<&Path>, from_file: Option<&Path>) {
+    if let Some(path) = to_file {
+        println!(
+            "dump       : {} > {}",
+            display_command(&archive_dump_command(plan)),
+            path.display()
+        );
+        if let Some([collection]) = plan.collections.as_deref() {
+            print_query(plan, collection);
+        }
+    }
+    if let Some(path) = from_file {
+        println!(
+            "restore    : {} < {}",
+            display_command(&restore_command(plan)),
+            path.display()
+        );
+    }
+}
+
+pub fn print_pipelines(plan: &DumpPlan) {
+    let targets = pipeline_targets(plan);
+    let restore_command = restore_command(plan);
+
+    println!("pipelines ({}):", targets.len());
+    for collection in targets {
+        println!(
+            "  {} | {}",
+            display_command(&dump_command(plan, collection.as_deref())),
+            display_command(&restore_command)
+        );
+        if let Some(collection) = &collection {
+            print_query(plan, collection);
+        }
+    }
+}
+
+/// `--queryFile`은 실행할 때 만들어지므로 dry-run에서는 그 내용을 따로 보여줍니다.
+fn print_query(plan: &DumpPlan, collection: &str) {
+    if let Some(query) = plan.queries.get(collection) {
+        println!("    query: {}", query);
+    }
+}
+
+/// mongorestore 로그의 `finished restoring db.col (N documents, M failures)` 줄을 읽습니다.
+fn parse_finished(line: &str, target_db: &str) -> Option<(String, u64, u64)> {
+    let (_, restored) = line.split_once("finished restoring ")?;
+    let (namespace, counts) = restored.split_once(" (")?;
+    let mut numbers = counts
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse::<u64>());
+    let documents = numbers.next()?.ok()?;
+    let failures = numbers.next().and_then(|n| n.ok()).unwrap_or_default();
+    let name = namespace
+        .strip_prefix(&format!("{}.", target_db))
+        .unwrap_or(namespace);
+
+    Some((name.to_string(), documents, failures))
+}
+
+/// mongodump/mongorestore 로그 앞의 timestamp를 뗍니다.
+pub fn strip_timestamp(line: &str) -> &str {
+    line.rsplit('\t').next().unwrap_or(line).trim()
+}
+
+pub fn read_lines(
+    stream: Option<impl Read + Send + 'static>,
+    bar: Option<ProgressBar>,
+) -> JoinHandle<Vec<String>> {
+    thread::spawn(move || {
+        let Some(stream) = stream else {
+            return vec![];
+        };
+
+        BufReader::new(stream)
+            .lines()
+            .map_while(Result::ok)
+            .inspect(|line| {
+                if let Some(bar) = &bar {
+                    bar.set_message(strip_timestamp(line).to_string());
+                }
+            })
+            .collect()
+    })
+}
+
+/// 한 번의 mongodump | mongorestore 실행 결과
+struct PipelineOutcome {
+    bytes: u64,
+    restore_log: Vec<String>,
+    errors: Vec<String>,
+}
+
+pub fn exit_error(program: &str, status: ExitStatus, log: &[String]) -> Option<String> {
+    (!status.success()).then(|| {
+        format!(
+            "{} exited with {}: {}",
+            program,
+            status,
+            log.last().map_or("", |line| strip_timestamp(line))
+        )
+    })
+}
+
+fn execute(
+    plan: &DumpPlan,
+    collection: Option<&str>,
+    bar: &ProgressBar,
+    limiter: &Option<Arc<RateLimiter>>,
+) -> PipelineOutcome {
+    let mut dump_child = match dump_command(plan, collection)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            return PipelineOutcome {
+                bytes: 0,
+                restore_log: vec![],
+                errors: vec![format!("failed to launch {}: {}", MONGO_DUMP, e)],
+            }
+        }
+    };
+    let mut restore_child = match restore_command(plan)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = dump_child.kill();
+            let _ = dump_child.wait();
+
+            return PipelineOutcome {
+                bytes: 0,
+                restore_log: vec![],
+                errors: vec![format!("failed to launch {}: {}", MONGO_RESTORE, e)],
+            };
+        }
+    };
+
+    let dump_log = read_lines(dump_child.stderr.take(), None);
+    let restore_log = read_lines(restore_child.stderr.take(), Some(bar.clone()));
+
+    let mut archive = ThrottledReader::new(
+        dump_child
+            .stdout
+            .take()
+            .expect("fail to open mongodump stdout"),
+        limiter.clone(),
+    );
+    let restore_stdin = restore_child
+        .stdin
+        .take()
+        .expect("fail to open mongorestore stdin");
+    // 어느 한쪽이 먼저 죽으면 copy가 실패하므로, 성공 여부는 아래의 exit status로 판단합니다.
+    let bytes =
+        io::copy(&mut archive, &mut bar.wrap_write(restore_stdin)).unwrap_or(bar.position());
+    // mongorestore가 먼저 죽었다면 mongodump가 막히지 않도록 남은 archive를 비웁니다.
+    let _ = io::copy(&mut archive, &mut io::sink());
+
+    let restore_status = restore_child.wait().expect("failed to wait mongorestore");
+    let dump_status = dump_child.wait().expect("failed to wait mongodump");
+
+    let dump_log = dump_log.join().expect("failed to read mongodump log");
+    let restore_log = restore_log.join().expect("failed to read mongorestore log");
+
+    let errors = [
+        exit_error(MONGO_DUMP, dump_status, &dump_log),
+        exit_error(MONGO_RESTORE, restore_status, &restore_log),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    PipelineOutcome {
+        bytes,
+        restore_log,
+        errors,
+    }
+}
+
+/// mongorestore 로그에서 `db.collection`에 난 duplicate key(E11000) 오류 수.
+/// bulk write 오류는 한 줄에 여러 개가 이어서 찍히므로 줄이 아니라 오류마다 셉니다.
+fn duplicate_key_failures(restore_log: &[String], namespace: &str) -> u64 {
+    restore_log
+        .iter()
+        .map(|line| {
+            line.split(DUPLICATE_KEY_ERROR)
+                .skip(1)
+                .filter(|rest| rest.split_whitespace().next() == Some(namespace))
+                .count() as u64
+        })
+        .sum()
+}
+
+/// `--drop` 없이 복원하면 target에 이미 있는 `_id`가 duplicate key로 실패하므로,
+/// native engine의 merge처럼 그만큼은 오류로 보지 않고 나머지 실패만 알립니다.
+fn failures_error(
+    plan: &DumpPlan,
+    collection: &str,
+    failures: u64,
+    restore_log: &[String],
+) -> Option<String> {
+    let duplicates = if plan.drop {
+        0
+    } else {
+        duplicate_key_failures(restore_log, &format!("{}.{}", plan.target_db, collection))
+    };
+    let failures = failures.saturating_sub(duplicates);
+
+    (failures > 0).then(|| format!("{} document(s) failed to restore", failures))
+}
+
+fn run_pipeline(
+    plan: &DumpPlan,
+    collection: Option<&str>,
+    label: &str,
+    bar: &ProgressBar,
+    limiter: &Option<Arc<RateLimiter>>,
+) -> Vec<CollectionReport> {
+    let started_at = Instant::now();
+    let outcome = execute(plan, collection, bar, limiter);
+    let elapsed = started_at.elapsed();
+    let finished: Vec<(String, u64, u64)> = outcome
+        .restore_log
+        .iter()
+        .filter_map(|line| parse_finished(line, &plan.target_db))
+        .collect();
+    let pipeline_error = (!outcome.errors.is_empty()).then(|| outcome.errors.join("; "));
+
+    match collection {
+        Some(name) => {
+            let documents = finished.iter().map(|(_, documents, _)| documents).sum();
+            let failures = finished.iter().map(|(_, _, failures)| failures).sum();
+
+            vec![CollectionReport {
+                name: name.to_string(),
+                documents,
+                bytes: Some(outcome.bytes),
+                elapsed,
+                attempts: 1,
+                error: pipeline_error
+                    .or_else(|| failures_error(plan, name, failures, &outcome.restore_log)),
+            }]
+        }
+        None => {
+            let mut reports: Vec<CollectionReport> = finished
+                .into_iter()
+                .map(|(name, documents, failures)| CollectionReport {
+                    error: failures_error(plan, &name, failures, &outcome.restore_log),
+                    name,
+                    documents,
+                    bytes: None,
+                    elapsed,
+                    attempts: 1,
+                })
+                .collect();
+
+            if let Some(error) = pipeline_error {
+                reports.push(CollectionReport {
+                    name: label.to_string(),
+                    documents: 0,
+                    bytes: Some(outcome.bytes),
+                    elapsed,
+                    attempts: 1,
+                    error: Some(error),
+                });
+            }
+
+            reports
+        }
+    }
+}
+
+/// 실패하면 설정된 횟수만큼 pipeline을 처음부터 다시 실행합니다. `--drop`이면 collection을 지우고 다시 넣고,
+/// `--no-drop`이면 이미 들어간 document를 건너뛰므로 재실행해도 안전합니다.
+fn run_with_retries(
+    plan: &DumpPlan,
+    collection: Option<&str>,
+    label: &str,
+    bar: &ProgressBar,
+    limiter: &Option<Arc<RateLimiter>>,
+) -> Vec<CollectionReport> {
+    let retries = plan.retries_for(label);
+    let started_at = Instant::now();
+    let mut attempts = 1;
+
+    loop {
+        let mut reports = run_pipeline(plan, collection, label, bar, limiter);
+        let succeeded = reports.iter().all(CollectionReport::succeeded);
+
+        if succeeded || attempts > retries {
+            reports.iter_mut().for_each(|report| {
+                report.attempts = attempts;
+                report.elapsed = started_at.elapsed();
+            });
+            progress::finish(bar, succeeded);
+
+            return reports;
+        }
+
+        bar.reset();
+        bar.set_message(format!("retry {}/{}", attempts, retries));
+        attempts += 1;
+    }
+}
+
+/// `plan.jobs`개의 worker thread가 queue에서 pipeline을 하나씩 꺼내 실행합니다.
+pub fn run(plan: &Arc<DumpPlan>) -> Vec<CollectionReport> {
+    let _query_files = QueryFiles::write(plan).unwrap_or_else(|e| exit_with_error!("{}", e));
+
+    let multi = MultiProgress::new();
+    let queue: VecDeque<(Option<String>, String)> = pipeline_targets(plan)
+        .into_iter()
+        .map(|collection| {
+            let label = collection
+                .clone()
+                .unwrap_or_else(|| format!("{}.*", plan.source_db));
+
+            (collection, label)
+        })
+        .collect();
+    let workers = plan.jobs.clamp(1, queue.len().max(1));
+    let queue = Arc::new(Mutex::new(queue));
+    let limiter = RateLimiter::new(&plan.throttle);
+    let pause = throttle::pause(&plan.throttle);
+    let mut handles = vec![];
+
+    for _ in 0..workers {
+        let plan = Arc::clone(plan);
+        let queue = Arc::clone(&queue);
+        let limiter = limiter.clone();
+        let multi = multi.clone();
+
+        handles.push(thread::spawn(move || {
+            let mut reports = vec![];
+
+            loop {
+                let next = queue.lock().expect("dump queue poisoned").pop_front();
+                let Some((collection, label)) = next else {
+                    break;
+                };
+                // 기다리는 pipeline까지 bar를 그리지 않도록 꺼낼 때 만듭니다.
+                let bar = progress::stream_bar(&multi, &label);
+
+                // worker마다 두 번째 pipeline부터는 쉬었다 시작합니다.
+                if !reports.is_empty() && !pause.is_zero() {
+                    bar.set_message("pausing");
+                    thread::sleep(pause);
+                }
+                reports.extend(run_with_retries(
+                    &plan,
+                    collection.as_deref(),
+                    &label,
+                    &bar,
+                    &limiter,
+                ));
+            }
+
+            reports
+        }));
+    }
+
+    handles
+        .into_iter()
+        .flat_map(|handle| handle.join().expect("dump pipeline panicked"))
+        .collect()
+}
+
+/// mongodump archive(gzip)를 파일로 저장하고 쓴 byte 수를 돌려줍니다. 실패하면 쓰다 만 파일은 지웁니다.
+pub fn run_to_file(plan: &DumpPlan, path: &Path) -> Result<u64, String> {
+    let multi = MultiProgress::new();
+    let _query_files = QueryFiles::write(plan)?;
+    let bar = progress::stream_bar(&multi, &path.display().to_string());
+    let file =
+        File::create(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+    let mut dump_child = archive_dump_command(plan)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to launch {}: {}", MONGO_DUMP, e))?;
+
+    let dump_log = read_lines(dump_child.stderr.take(), Some(bar.clone()));
+    let mut archive = ThrottledReader::new(
+        dump_child
+            .stdout
+            .take()
+            .expect("fail to open mongodump stdout"),
+        RateLimiter::new(&plan.throttle),
+    );
+    let mut writer = BufWriter::new(file);
+    // BufWriter를 drop할 때의 flush 오류는 버려지므로, 직접 flush하고 디스크에 쓴 것까지 확인합니다.
+    let written = io::copy(&mut archive, &mut bar.wrap_write(&mut writer)).and_then(|bytes| {
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+
+        Ok(bytes)
+    });
+    let dump_status = dump_child.wait().expect("failed to wait mongodump");
+    let dump_log = dump_log.join().expect("failed to read mongodump log");
+
+    let result = match (written, exit_error(MONGO_DUMP, dump_status, &dump_log)) {
+        (_, Some(error)) => Err(error),
+        (Err(e), None) => Err(format!("failed to write {}: {}", path.display(), e)),
+        (Ok(bytes), None) => Ok(bytes),
+    };
+
+    progress::finish(&bar, result.is_ok());
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+
+    result
+}
+
+/// `run_to_file`로 만든 archive를 target에 복원합니다. namespace는 dump할 때와 같이 바꿉니다.
+pub fn run_from_file(plan: &DumpPlan, path: &Path) -> Vec<CollectionReport> {
+    let started_at = Instant::now();
+    let failed = |error: String| {
+        vec![CollectionReport {
+            name: path.display().to_string(),
+            documents: 0,
+            bytes: None,
+            elapsed: started_at.elapsed(),
+            attempts: 1,
+            error: Some(error),
+        }]
+    };
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return failed(format!("failed to open {}: {}", path.display(), e)),
+    };
+    let multi = MultiProgress::new();
+    let bar = progress::bytes_bar(
+        &multi,
+        &path.display().to_string(),
+        file.metadata().map(|meta| meta.len()).unwrap_or_default(),
+    );
+    let mut restore_child = match restore_command(plan)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return failed(format!("failed to launch {}: {}", MONGO_RESTORE, e)),
+    };
+
+    let restore_log = read_lines(restore_child.stderr.take(), Some(bar.clone()));
+    let restore_stdin = restore_child
+        .stdin
+        .take()
+        .expect("fail to open mongorestore stdin");
+    let bytes = io::copy(&mut bar.wrap_read(file), &mut BufWriter::new(restore_stdin))
+        .unwrap_or(bar.position());
+    let restore_status = restore_child.wait().expect("failed to wait mongorestore");
+    let restore_log = restore_log.join().expect("failed to read mongorestore log");
+    let elapsed = started_at.elapsed();
+
+    let mut reports: Vec<CollectionReport> = restore_log
+        .iter()
+        .filter_map(|line| parse_finished(line, &plan.target_db))
+        .map(|(name, documents, failures)| CollectionReport {
+            error: failures_error(plan, &name, failures, &restore_log),
+            name,
+            documents,
+            bytes: None,
+            elapsed,
+            attempts: 1,
+        })
+        .collect();
+
+    if let Some(error) = exit_error(MONGO_RESTORE, restore_status, &restore_log) {
+        reports.push(CollectionReport {
+            name: path.display().to_string(),
+            documents: 0,
+            bytes: Some(bytes),
+            elapsed,
+            attempts: 1,
+            error: Some(error),
+        });
+    }
+
+    progress::finish(&bar, reports.iter().all(CollectionReport::succeeded));
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tolerates_only_duplicate_keys_without_drop() {
+        let restore_log = vec![
+            "2024-05-01T10:00:00.000+0900\tcontinuing through error: bulk write exception: write errors: [E11000 duplicate key error collection: app_dev.users index: _id_ dup key: { _id: 1 }, E11000 duplicate key error collection: app_dev.users index: _id_ dup key: { _id: 2 }]".to_string(),
+            "2024-05-01T10:00:00.000+0900\tcontinuing through error: E11000 duplicate key error collection: app_dev.orders index: _id_ dup key: { _id: 3 }".to_string(),
+            "2024-05-01T10:00:00.000+0900\tcontinuing through error: Document failed validation".to_string(),
+        ];
+        let mut plan = DumpPlan {
+            target_db: "app_dev".to_string(),
+            drop: false,
+            ..Default::default()
+        };
+
+        assert_eq!(duplicate_key_failures(&restore_log, "app_dev.users"), 2);
+        assert_eq!(failures_error(&plan, "users", 2, &restore_log), None);
+        assert_eq!(
+            failures_error(&plan, "users", 3, &restore_log).as_deref(),
+            Some("1 document(s) failed to restore")
+        );
+        assert!(failures_error(&plan, "orders", 1, &restore_log).is_none());
+
+        plan.drop = true;
+        assert!(failures_error(&plan, "users", 2, &restore_log).is_some());
+    }
+}
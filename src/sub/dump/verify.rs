@@ -0,0 +1,240 @@
+use super::{native, DumpPlan};
+use crate::exit_with_error;
+use colored::Colorize;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Database,
+};
+use std::collections::BTreeMap;
+// 서버 버전에 따라 달라지는 값이라 비교하지 않습니다.
+const IGNORED_INDEX_FIELDS: [&str; 2] = ["v", "ns"];
+
+/// collection 하나를 source와 target에서 비교한 결과
+pub struct CollectionDiff {
+    pub name: String,
+    pub differences: Vec<String>,
+}
+
+/// `listCollections`/`listIndexes` 결과의 첫 batch를 그대로 읽습니다.
+async fn first_batch(db: &Database, command: Document) -> mongodb::error::Result<Vec<Document>> {
+    let result = db.run_command(command).await?;
+    let batch = result
+        .get_document("cursor")
+        .and_then(|cursor| cursor.get_array("firstBatch"))
+        .map(|batch| {
+            batch
+                .iter()
+                .filter_map(|item| item.as_document().cloned())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(batch)
+}
+
+/// collection 정보(`type`, `options` 포함). collection이 없으면 `None`
+async fn collection_info(db: &Database, name: &str) -> mongodb::error::Result<Option<Document>> {
+    let batch = first_batch(
+        db,
+        doc! { "listCollections": 1, "filter": { "name": name } },
+    )
+    .await?;
+
+    Ok(batch.into_iter().next())
+}
+
+/// index 이름 → 정의
+async fn indexes(db: &Database, name: &str) -> mongodb::error::Result<BTreeMap<String, Document>> {
+    let batch = first_batch(db, doc! { "listIndexes": name }).await?;
+
+    Ok(batch
+        .into_iter()
+        .map(|mut index| {
+            IGNORED_INDEX_FIELDS.iter().for_each(|field| {
+                index.remove(field);
+            });
+            let name = index.get_str("name").unwrap_or_default().to_string();
+
+            (name, index)
+        })
+        .collect())
+}
+
+async fn count(db: &Database, name: &str, filter: Document) -> mongodb::error::Result<u64> {
+    let collection = db.collection::<Document>(name);
+
+    if filter.is_empty() {
+        collection.estimated_document_count().await
+    } else {
+        collection.count_documents(filter).await
+    }
+}
+
+fn compact(value: &Bson) -> String {
+    value.clone().into_relaxed_extjson().to_string()
+}
+
+/// 양쪽 document의 key마다 값이 다르면 `label key: source ..., target ...` 형태로 모읍니다.
+fn compare_fields(
+    label: &str,
+    source: &Document,
+    target: &Document,
+    differences: &mut Vec<String>,
+) {
+    let mut keys: Vec<&String> = source.keys().chain(target.keys()).collect();
+
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        match (source.get(key), target.get(key)) {
+            (Some(source), Some(target)) if source == target => {}
+            (source, target) => differences.push(format!(
+                "{} {}: source {}, target {}",
+                label,
+                key,
+                source.map_or("(none)".to_string(), compact),
+                target.map_or("(none)".to_string(), compact)
+            )),
+        }
+    }
+}
+
+async fn compare_collection(
+    plan: &DumpPlan,
+    source_db: &Database,
+    target_db: &Database,
+    name: &str,
+) -> mongodb::error::Result<Vec<String>> {
+    let mut differences = vec![];
+    let Some(source_info) = collection_info(source_db, name).await? else {
+        return Ok(vec![format!("not found in source {}", plan.source_db)]);
+    };
+    let Some(target_info) = collection_info(target_db, name).await? else {
+        return Ok(vec![format!("missing in target {}", plan.target_db)]);
+    };
+
+    compare_fields(
+        "type",
+        &doc! { "type": source_info.get("type").cloned().unwrap_or(Bson::Null) },
+        &doc! { "type": target_info.get("type").cloned().unwrap_or(Bson::Null) },
+        &mut differences,
+    );
+    compare_fields(
+        "option",
+        &source_info
+            .get_document("options")
+            .cloned()
+            .unwrap_or_default(),
+        &target_info
+            .get_document("options")
+            .cloned()
+            .unwrap_or_default(),
+        &mut differences,
+    );
+
+    // view에는 document와 index가 따로 없습니다.
+    if source_info.get_str("type") == Ok("view") {
+        return Ok(differences);
+    }
+
+    let filter = plan
+        .queries
+        .get(name)
+        .map(|query| native::parse_query(name, query))
+        .unwrap_or_default();
+    let mut expected = count(source_db, name, filter).await?;
+    if let Some(size) = plan.limit.or(plan.sample) {
+        expected = expected.min(size);
+    }
+    let actual = count(target_db, name, Document::new()).await?;
+
+    // drop하지 않고 합친 경우 target에 원래 있던 document가 남아 있을 수 있습니다.
+    if actual != expected && (plan.drop || actual < expected) {
+        differences.push(format!(
+            "documents: expected {}{}, target {}",
+            if plan.drop { "" } else { "at least " },
+            expected,
+            actual
+        ));
+    }
+
+    let source_indexes = indexes(source_db, name).await?;
+    let target_indexes = indexes(target_db, name).await?;
+
+    for (index, definition) in &source_indexes {
+        match target_indexes.get(index) {
+            Some(target) if target == definition => {}
+            Some(target) => compare_fields(
+                &format!("index {}", index),
+                definition,
+                target,
+                &mut differences,
+            ),
+            None => differences.push(format!("index {}: missing in target", index)),
+        }
+    }
+    target_indexes
+        .keys()
+        .filter(|index| !source_indexes.contains_key(*index))
+        .for_each(|index| differences.push(format!("index {}: only in target", index)));
+
+    Ok(differences)
+}
+
+pub async fn run(plan: &DumpPlan) -> Vec<CollectionDiff> {
+    let source_db = native::connect(&plan.source_uri, &plan.source_db).await;
+    let target_db = native::connect(&plan.target_uri, &plan.target_db).await;
+    let collections = match &plan.collections {
+        Some(cols) => cols.clone(),
+        None => native::collection_names(&plan.source_uri, &plan.source_db)
+            .await
+            .into_iter()
+            .filter(|col| !plan.excludes.contains(col))
+            .collect(),
+    };
+    let mut diffs = vec![];
+
+    for name in collections {
+        let differences = compare_collection(plan, &source_db, &target_db, &name)
+            .await
+            .unwrap_or_else(|e| vec![format!("failed to compare: {}", e)]);
+
+        diffs.push(CollectionDiff { name, differences });
+    }
+
+    diffs
+}
+
+pub fn print_diffs(plan: &DumpPlan, diffs: &[CollectionDiff]) {
+    println!(
+        "verify {}.* -> {}.* ({} collection(s))",
+        plan.source_db,
+        plan.target_db,
+        diffs.len()
+    );
+    for diff in diffs {
+        if diff.differences.is_empty() {
+            println!("  {} {}", "✔".green(), diff.name);
+            continue;
+        }
+
+        println!("  {} {}", "✘".red(), diff.name.bold());
+        diff.differences
+            .iter()
+            .for_each(|difference| println!("      {}", difference));
+    }
+}
+
+pub fn exit_if_different(diffs: &[CollectionDiff]) {
+    let different = diffs
+        .iter()
+        .filter(|diff| !diff.differences.is_empty())
+        .count();
+
+    if different > 0 {
+        exit_with_error!(
+            "{} collection(s) differ between source and target",
+            different
+        )
+    }
+}
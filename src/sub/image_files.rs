@@ -0,0 +1,68 @@
+use crate::exit_with_error;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use std::{
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// `input_dir`에서 `extensions` 중 하나로 끝나는 파일을 `input_dir` 기준 상대 경로로 돌려줍니다.
+/// `recursive`가 아니면 바로 아래 파일만 보고, 실행할 때마다 같은 순서가 되도록 이름순으로 정렬합니다.
+pub fn collect(input_dir: &Path, recursive: bool, extensions: &[&str]) -> Vec<PathBuf> {
+    WalkDir::new(input_dir)
+        .min_depth(1)
+        .max_depth(if recursive { usize::MAX } else { 1 })
+        .sort_by_file_name()
+        .into_iter()
+        .map(|result_entry| {
+            result_entry.unwrap_or_else(|e| exit_with_error!("이미지 디렉토리 읽기 실패:\n{}", e))
+        })
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .and_then(|os_str| os_str.to_str())
+                .is_some_and(|ext| extensions.contains(&ext))
+        })
+        .map(|entry| {
+            entry
+                .path()
+                .strip_prefix(input_dir)
+                .expect("walkdir entry outside input dir")
+                .to_path_buf()
+        })
+        .collect()
+}
+
+/// `--output-dir` 아래에 원본과 같은 디렉토리 구조를 만들어 둡니다.
+pub fn create_parent_dir(output_path: &Path) -> Result<(), String> {
+    match output_path.parent() {
+        Some(parent) => create_dir_all(parent)
+            .map_err(|e| format!("출력 디렉토리 생성 실패: {}\n{}", parent.display(), e)),
+        None => Ok(()),
+    }
+}
+
+/// `jobs`개의 thread(주지 않으면 CPU core 수)가 `targets`를 나눠 처리합니다.
+/// 먼저 끝난 thread가 남은 파일을 가져가지만, 결과는 `targets` 순서대로 돌려줍니다.
+pub fn process_parallel<T, R, F>(targets: &[T], jobs: Option<usize>, process: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or_default())
+        .build()
+        .unwrap_or_else(|e| exit_with_error!("작업 thread 생성 실패:\n{}", e));
+
+    pool.install(|| targets.par_iter().map(&process).collect())
+}
+
+/// 모든 파일을 처리한 뒤에 실패가 있었으면 개수를 알리고 exit code 1로 끝냅니다.
+pub fn exit_if_failed(failed: usize, total: usize) {
+    if failed > 0 {
+        exit_with_error!("{}개 중 {}개 파일 처리 실패", total, failed);
+    }
+}
@@ -1,16 +1,28 @@
-use crate::{cli::ResizeCommand, exit_with_error};
+use crate::{
+    cli::{Gravity, ResizeCommand, ResizeFilter, ResizeMode},
+    exit_with_error,
+    sub::image_files,
+};
 use fast_image_resize as fr;
 use image::{
     codecs::{jpeg::JpegEncoder, png::PngEncoder},
     io::Reader as ImageReader,
     ColorType, ImageEncoder,
 };
+use serde::Serialize;
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{BufWriter, Write},
     num::NonZeroU32,
     path::{self, PathBuf},
 };
+const DEFAULT_VARIANT_TEMPLATE: &str = "{stem}@{w}w.{ext}";
+const DEFAULT_DENSITY_TEMPLATE: &str = "{stem}@{d}x.{ext}";
+// super-sampling은 nearest로 결과의 이 배수 크기까지 줄인 뒤 convolution을 합니다.
+const SUPER_SAMPLING_MULTIPLICITY: u8 = 2;
+// auto에서 이 배율 이상 줄이면 super-sampling을 씁니다.
+const AUTO_SUPER_SAMPLING_SCALE: f64 = 3.0;
 
 #[derive(Debug)]
 enum ImageType {
@@ -24,16 +36,143 @@ struct ImageMeta {
     image_type: ImageType,
 }
 
-fn resize<'a>(
-    work_dir: &mut PathBuf,
-    file_name: &str,
-    target_width: u32,
-    target_height: u32,
-) -> (fast_image_resize::Image<'a>, NonZeroU32, NonZeroU32) {
+/// 결과 파일 하나. manifest의 경로는 `--output-dir` 기준입니다.
+#[derive(Serialize)]
+struct Variant {
+    path: String,
+    width: u32,
+    height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    density: Option<u32>,
+}
+
+impl Variant {
+    /// `--densities`로 만든 결과는 `2x`, 나머지는 `640w` descriptor를 씁니다.
+    fn srcset_candidate(&self) -> String {
+        match self.density {
+            Some(density) => format!("{} {}x", self.path, density),
+            None => format!("{} {}w", self.path, self.width),
+        }
+    }
+}
+
+/// 만들 결과 하나의 요청 크기
+struct Target {
+    width: Option<u32>,
+    height: Option<u32>,
+    density: Option<u32>,
+}
+
+/// 원본 파일 하나에서 만든 결과들. `srcset`은 `<img srcset>`에 그대로 넣을 수 있습니다.
+#[derive(Serialize)]
+struct ManifestEntry {
+    srcset: String,
+    variants: Vec<Variant>,
+}
+
+/// 원본을 어느 쪽으로 치우쳐 자를지. (0, 0)이 왼쪽 위, (1, 1)이 오른쪽 아래입니다.
+fn centering(gravity: Gravity) -> (f32, f32) {
+    match gravity {
+        Gravity::Center => (0.5, 0.5),
+        Gravity::North => (0.5, 0.0),
+        Gravity::South => (0.5, 1.0),
+        Gravity::East => (1.0, 0.5),
+        Gravity::West => (0.0, 0.5),
+        Gravity::NorthEast => (1.0, 0.0),
+        Gravity::NorthWest => (0.0, 0.0),
+        Gravity::SouthEast => (1.0, 1.0),
+        Gravity::SouthWest => (0.0, 1.0),
+    }
+}
+
+fn scaled(length: u32, scale: f64) -> u32 {
+    ((length as f64 * scale).round() as u32).max(1)
+}
+
+/// 원본 크기와 옵션으로 결과 크기를 정합니다. 한 쪽만 주면 mode와 상관없이 비율을 지킵니다(crop은 그 쪽만 자름).
+fn target_size(
+    src_width: u32,
+    src_height: u32,
+    width: Option<u32>,
+    height: Option<u32>,
+    mode: ResizeMode,
+) -> (u32, u32) {
+    match (mode, width, height) {
+        (ResizeMode::Crop, _, _) => (
+            width.unwrap_or(src_width).min(src_width),
+            height.unwrap_or(src_height).min(src_height),
+        ),
+        (_, Some(width), None) => (width, scaled(src_height, width as f64 / src_width as f64)),
+        (_, None, Some(height)) => (scaled(src_width, height as f64 / src_height as f64), height),
+        (ResizeMode::Fit, Some(width), Some(height)) => {
+            let scale = (width as f64 / src_width as f64).min(height as f64 / src_height as f64);
+
+            (scaled(src_width, scale), scaled(src_height, scale))
+        }
+        (_, Some(width), Some(height)) => (width, height),
+        (_, None, None) => (src_width, src_height),
+    }
+}
+
+/// crop은 gravity 쪽에서 결과 크기만큼 잘라냅니다.
+fn crop_box(
+    src_width: u32,
+    src_height: u32,
+    dst_width: NonZeroU32,
+    dst_height: NonZeroU32,
+    gravity: Gravity,
+) -> fr::CropBox {
+    let (x, y) = centering(gravity);
+
+    fr::CropBox {
+        left: ((src_width - dst_width.get()) as f32 * x).round() as u32,
+        top: ((src_height - dst_height.get()) as f32 * y).round() as u32,
+        width: dst_width,
+        height: dst_height,
+    }
+}
+
+/// `--filter`를 실제 알고리즘으로 바꿉니다. auto는 crop한 원본 영역이 결과보다 몇 배 큰지 봅니다.
+fn resize_alg(
+    filter: ResizeFilter,
+    crop_box: fr::CropBox,
+    dst_width: NonZeroU32,
+    dst_height: NonZeroU32,
+) -> fr::ResizeAlg {
+    let super_sampling =
+        fr::ResizeAlg::SuperSampling(fr::FilterType::Lanczos3, SUPER_SAMPLING_MULTIPLICITY);
+
+    match filter {
+        ResizeFilter::Auto => {
+            let scale = (crop_box.width.get() as f64 / dst_width.get() as f64)
+                .min(crop_box.height.get() as f64 / dst_height.get() as f64);
+
+            if scale >= AUTO_SUPER_SAMPLING_SCALE {
+                super_sampling
+            } else if scale < 1.0 {
+                fr::ResizeAlg::Convolution(fr::FilterType::CatmullRom)
+            } else {
+                fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3)
+            }
+        }
+        ResizeFilter::Nearest => fr::ResizeAlg::Nearest,
+        ResizeFilter::Bilinear => fr::ResizeAlg::Convolution(fr::FilterType::Bilinear),
+        ResizeFilter::CatmullRom => fr::ResizeAlg::Convolution(fr::FilterType::CatmullRom),
+        ResizeFilter::Mitchell => fr::ResizeAlg::Convolution(fr::FilterType::Mitchell),
+        ResizeFilter::Lanczos3 => fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3),
+        ResizeFilter::SuperSampling => super_sampling,
+    }
+}
+
+/// 한 번만 decode해서 여러 크기로 resize할 수 있도록 alpha를 곱해 둔 원본
+fn decode<'a>(work_dir: &mut PathBuf, file_name: &str) -> Result<fr::Image<'a>, String> {
     work_dir.push(file_name);
-    let img = ImageReader::open(work_dir).unwrap().decode().unwrap();
-    let width = NonZeroU32::new(img.width()).unwrap();
-    let height = NonZeroU32::new(img.height()).unwrap();
+    let img = ImageReader::open(work_dir)
+        .map_err(|e| format!("이미지 파일 열기 실패: {}", e))?
+        .decode()
+        .map_err(|e| format!("이미지 decode 실패: {}", e))?;
+    let width = NonZeroU32::new(img.width()).ok_or("너비가 0인 이미지입니다")?;
+    let height = NonZeroU32::new(img.height()).ok_or("높이가 0인 이미지입니다")?;
     let mut src_image = fr::Image::from_vec_u8(
         width,
         height,
@@ -42,28 +181,230 @@ fn resize<'a>(
     )
     .unwrap();
 
-    let alpha_mul_div = fr::MulDiv::default();
-    alpha_mul_div
+    fr::MulDiv::default()
         .multiply_alpha_inplace(&mut src_image.view_mut())
         .unwrap();
 
+    Ok(src_image)
+}
+
+fn resize<'a>(
+    src_image: &fr::Image,
+    target_width: Option<u32>,
+    target_height: Option<u32>,
+    resize_opts: &ResizeCommand,
+) -> (fast_image_resize::Image<'a>, NonZeroU32, NonZeroU32) {
+    let width = src_image.width();
+    let height = src_image.height();
+    let (target_width, target_height) = target_size(
+        width.get(),
+        height.get(),
+        target_width,
+        target_height,
+        resize_opts.mode,
+    );
     let dst_width = NonZeroU32::new(target_width).unwrap();
     let dst_height = NonZeroU32::new(target_height).unwrap();
     let mut dst_image = fr::Image::new(dst_width, dst_height, src_image.pixel_type());
 
     let mut dst_view = dst_image.view_mut();
+    let mut src_view = src_image.view();
 
-    let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3));
-    resizer.resize(&src_image.view(), &mut dst_view).unwrap();
+    match resize_opts.mode {
+        ResizeMode::Cover => src_view.set_crop_box_to_fit_dst_size(
+            dst_width,
+            dst_height,
+            Some(centering(resize_opts.gravity)),
+        ),
+        ResizeMode::Crop => src_view
+            .set_crop_box(crop_box(
+                width.get(),
+                height.get(),
+                dst_width,
+                dst_height,
+                resize_opts.gravity,
+            ))
+            .unwrap(),
+        ResizeMode::Fit | ResizeMode::Fill => {}
+    }
 
-    alpha_mul_div.divide_alpha_inplace(&mut dst_view).unwrap();
+    let mut resizer = fr::Resizer::new(resize_alg(
+        resize_opts.filter,
+        src_view.crop_box(),
+        dst_width,
+        dst_height,
+    ));
+    resizer.resize(&src_view, &mut dst_view).unwrap();
+
+    fr::MulDiv::default()
+        .divide_alpha_inplace(&mut dst_view)
+        .unwrap();
 
     (dst_image, dst_width, dst_height)
 }
 
+fn encode(
+    image_type: &ImageType,
+    dst_image: &fr::Image,
+    dst_width: NonZeroU32,
+    dst_height: NonZeroU32,
+) -> Result<Vec<u8>, String> {
+    let mut result_buf = BufWriter::new(Vec::new());
+
+    match image_type {
+        ImageType::Jpeg => JpegEncoder::new(&mut result_buf).write_image(
+            dst_image.buffer(),
+            dst_width.get(),
+            dst_height.get(),
+            ColorType::Rgba8,
+        ),
+        ImageType::Png => PngEncoder::new(&mut result_buf).write_image(
+            dst_image.buffer(),
+            dst_width.get(),
+            dst_height.get(),
+            ColorType::Rgba8,
+        ),
+    }
+    .map_err(|e| format!("이미지 encode 실패: {}", e))?;
+
+    Ok(result_buf.into_inner().unwrap())
+}
+
+/// `{stem}`, `{ext}`, `{w}`, `{h}`, `{d}`를 채워 결과 파일 이름을 만듭니다.
+fn output_name(template: &str, file_name: &str, width: u32, height: u32, density: u32) -> String {
+    let file_path = path::Path::new(file_name);
+    let stem = file_path
+        .file_stem()
+        .map_or(file_name.into(), |stem| stem.to_string_lossy());
+    let ext = file_path
+        .extension()
+        .map_or("".into(), |ext| ext.to_string_lossy());
+
+    template
+        .replace("{stem}", &stem)
+        .replace("{ext}", &ext)
+        .replace("{w}", &width.to_string())
+        .replace("{h}", &height.to_string())
+        .replace("{d}", &density.to_string())
+}
+
+/// 원본 하나를 한 번 decode해서 요청한 크기들로 저장합니다.
+/// 원본보다 커서 건너뛴 너비는 메시지로 돌려줍니다.
+fn resize_file(
+    image_meta: &ImageMeta,
+    resize_opts: &ResizeCommand,
+    name_template: &str,
+) -> Result<(ManifestEntry, Vec<String>), String> {
+    let src_image = decode(
+        &mut image_meta.work_dir.to_owned(),
+        image_meta.file_name.as_str(),
+    )?;
+    let mut skipped = vec![];
+    let (src_width, src_height) = (src_image.width().get(), src_image.height().get());
+    let targets: Vec<Target> = match (&resize_opts.widths, &resize_opts.densities) {
+        (Some(widths), _) => widths
+            .iter()
+            .filter(|width| {
+                let larger = **width > src_width;
+
+                if larger {
+                    skipped.push(format!(
+                        "{}: {}w는 원본({}px)보다 커서 건너뜁니다",
+                        image_meta.file_name, width, src_width
+                    ));
+                }
+                !larger
+            })
+            .map(|width| Target {
+                width: Some(*width),
+                height: None,
+                density: None,
+            })
+            .collect(),
+        (None, Some(densities)) => densities
+            .iter()
+            .filter_map(|density| {
+                // u32를 넘는 배수는 어떤 원본보다도 크므로 원본보다 큰 배수와 같이 건너뜁니다.
+                let scale = |side: Option<u32>| match side {
+                    Some(side) => side.checked_mul(*density).map(Some),
+                    None => Some(None),
+                };
+                let target = scale(resize_opts.width).zip(scale(resize_opts.height)).map(
+                    |(width, height)| Target {
+                        width,
+                        height,
+                        density: Some(*density),
+                    },
+                );
+                // 1x는 항상 만들고, 더 큰 배수는 원본을 늘려야 하면 건너뜁니다.
+                let larger = target.as_ref().is_none_or(|target| {
+                    *density > 1
+                        && (target.width.is_some_and(|width| width > src_width)
+                            || target.height.is_some_and(|height| height > src_height))
+                });
+
+                if larger {
+                    skipped.push(format!(
+                        "{}: {}x는 원본({}x{})보다 커서 건너뜁니다",
+                        image_meta.file_name, density, src_width, src_height
+                    ));
+                    return None;
+                }
+                target
+            })
+            .collect(),
+        (None, None) => vec![Target {
+            width: resize_opts.width,
+            height: resize_opts.height,
+            density: None,
+        }],
+    };
+    let mut variants = vec![];
+
+    for target in targets {
+        let (dst_image, dst_width, dst_height) =
+            resize(&src_image, target.width, target.height, resize_opts);
+        // 하위 디렉토리 안의 원본은 결과도 `--output-dir` 아래 같은 디렉토리에 둡니다.
+        let relative_path = path::Path::new(&image_meta.file_name).with_file_name(output_name(
+            name_template,
+            &image_meta.file_name,
+            dst_width.get(),
+            dst_height.get(),
+            target.density.unwrap_or(1),
+        ));
+        let output_path = resize_opts.output_dir.join(&relative_path);
+        let output_name = relative_path.to_string_lossy().into_owned();
+        let encoded = encode(&image_meta.image_type, &dst_image, dst_width, dst_height)?;
+
+        image_files::create_parent_dir(&output_path)?;
+        File::create(&output_path)
+            .and_then(|file| BufWriter::new(file).write_all(&encoded))
+            .map_err(|e| format!("파일 쓰기 실패: {}\n{}", output_path.display(), e))?;
+
+        variants.push(Variant {
+            path: output_name,
+            width: dst_width.get(),
+            height: dst_height.get(),
+            density: target.density,
+        });
+    }
+
+    Ok((
+        ManifestEntry {
+            srcset: variants
+                .iter()
+                .map(Variant::srcset_candidate)
+                .collect::<Vec<String>>()
+                .join(", "),
+            variants,
+        },
+        skipped,
+    ))
+}
+
 pub fn run_resize(resize_opts: ResizeCommand) {
     let work_dir = &resize_opts.input_dir;
-    let targets: Vec<ImageMeta> = if let Some(file_name) = resize_opts.file_name {
+    let targets: Vec<ImageMeta> = if let Some(file_name) = resize_opts.file_name.clone() {
         let ext = path::Path::new(&file_name)
             .extension()
             .and_then(|os_str| os_str.to_str());
@@ -85,74 +426,60 @@ pub fn run_resize(resize_opts: ResizeCommand) {
             ),
         }
     } else {
-        std::fs::read_dir(&resize_opts.input_dir)
-            .unwrap_or_else(|e| exit_with_error!("이미지 디렉토리 읽기 실패:\n{}", e))
-            .map(|result_entry| result_entry.unwrap().file_name())
-            .filter_map(|file_name| {
-                let ext = path::Path::new(&file_name)
-                    .extension()
-                    .and_then(|os_str| os_str.to_str());
-
-                match ext {
-                    Some("jpg") | Some("jpeg") => Some(ImageMeta {
-                        file_name: file_name.to_str().unwrap().to_string(),
-                        work_dir: work_dir.to_owned(),
-                        image_type: ImageType::Jpeg,
-                    }),
-                    Some("png") => Some(ImageMeta {
-                        file_name: file_name.to_str().unwrap().to_string(),
-                        work_dir: work_dir.to_owned(),
-                        image_type: ImageType::Png,
-                    }),
-                    _ => None,
-                }
+        image_files::collect(work_dir, resize_opts.recursive, &["jpg", "jpeg", "png"])
+            .into_iter()
+            .map(|file_path| ImageMeta {
+                image_type: match file_path.extension().and_then(|os_str| os_str.to_str()) {
+                    Some("png") => ImageType::Png,
+                    _ => ImageType::Jpeg,
+                },
+                file_name: file_path.to_string_lossy().into_owned(),
+                work_dir: work_dir.to_owned(),
             })
             .collect()
     };
 
-    for image_meta in targets {
-        let (dst_image, dst_width, dst_height) = resize(
-            &mut image_meta.work_dir.to_owned(),
-            image_meta.file_name.as_str(),
-            resize_opts.width,
-            resize_opts.height,
-        );
-
-        let mut result_buf = BufWriter::new(Vec::new());
-        let result_buf = match image_meta.image_type {
-            ImageType::Jpeg => {
-                JpegEncoder::new(&mut result_buf)
-                    .write_image(
-                        dst_image.buffer(),
-                        dst_width.get(),
-                        dst_height.get(),
-                        ColorType::Rgba8,
-                    )
-                    .unwrap();
-
-                result_buf
+    let name_template = resize_opts.name_template.clone().unwrap_or_else(|| {
+        if resize_opts.widths.is_some() {
+            DEFAULT_VARIANT_TEMPLATE.to_string()
+        } else if resize_opts.densities.is_some() {
+            DEFAULT_DENSITY_TEMPLATE.to_string()
+        } else {
+            "{stem}.{ext}".to_string()
+        }
+    });
+    let mut manifest: BTreeMap<String, ManifestEntry> = BTreeMap::new();
+
+    let results = image_files::process_parallel(&targets, resize_opts.jobs, |image_meta| {
+        resize_file(image_meta, &resize_opts, &name_template)
+    });
+    let mut failed = 0;
+
+    for (image_meta, result) in targets.iter().zip(results) {
+        match result {
+            Ok((entry, skipped)) => {
+                skipped.iter().for_each(|message| println!("{}", message));
+                manifest.insert(image_meta.file_name.clone(), entry);
             }
-            ImageType::Png => {
-                PngEncoder::new(&mut result_buf)
-                    .write_image(
-                        dst_image.buffer(),
-                        dst_width.get(),
-                        dst_height.get(),
-                        ColorType::Rgba8,
-                    )
-                    .unwrap();
-
-                result_buf
+            Err(e) => {
+                eprintln!("resize 실패 🔴: {}\n{}", image_meta.file_name, e);
+                failed += 1;
             }
-        };
+        }
+    }
 
-        let mut output_path = resize_opts.output_dir.to_owned();
-        output_path.push(image_meta.file_name);
+    if let Some(manifest_path) = &resize_opts.manifest {
+        let content =
+            serde_json::to_string_pretty(&manifest).expect("failed to serialize manifest");
 
-        let file = File::create(output_path).unwrap();
-        let mut file_writer = BufWriter::new(file);
-        file_writer
-            .write_all(&result_buf.into_inner().unwrap())
-            .unwrap();
+        std::fs::write(manifest_path, content + "\n").unwrap_or_else(|e| {
+            exit_with_error!(
+                "manifest 파일 쓰기 실패: {}\n{}",
+                manifest_path.display(),
+                e
+            )
+        });
     }
+
+    image_files::exit_if_failed(failed, targets.len());
 }
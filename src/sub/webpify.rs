@@ -2,59 +2,55 @@ use image::io::Reader as ImageReader;
 use image::{DynamicImage, EncodableLayout};
 use std::fs::File;
 use std::io::Write;
-use walkdir::WalkDir;
+use std::path::Path;
 use webp::{Encoder, WebPMemory};
 
 use crate::cli::WebpifyCommand;
-use crate::exit_with_error;
+use crate::sub::image_files;
 
-fn is_jpeg_or_png(ext: &str) -> bool {
-    ext == "png" || ext == "jpeg" || ext == "jpg"
+/// Convert one image under `input_dir` and write it to the same relative path under `output_dir`.
+fn webpify_file(input_dir: &Path, output_dir: &Path, file_path: &Path) -> Result<(), String> {
+    // Open path as DynamicImage
+    let dyn_image: DynamicImage = ImageReader::open(input_dir.join(file_path))
+        .and_then(|img| img.with_guessed_format()) //ImageReader::with_guessed_format() function guesses if image needs to be opened in JPEG or PNG format.
+        .map_err(|e| format!("Error: {}", e))?
+        .decode()
+        .map_err(|e| format!("Error: {}", e))?;
+
+    // Make webp::Encoder from DynamicImage.
+    let encoder: Encoder = Encoder::from_image(&dyn_image).map_err(|e| format!("Error: {}", e))?;
+    // Encode image into WebPMemory.
+    let encoded_webp: WebPMemory = encoder.encode(65f32);
+    // Keep the relative directory of the original image under the output dir.
+    let webp_image_path = output_dir.join(file_path).with_extension("webp");
+
+    image_files::create_parent_dir(&webp_image_path)?;
+
+    // Make File-stream for WebP-result and write bytes into it.
+    File::create(&webp_image_path)
+        .and_then(|mut webp_image| webp_image.write_all(encoded_webp.as_bytes()))
+        .map_err(|e| format!("Error: {}: {}", webp_image_path.display(), e))
 }
 
 pub fn run_webpify(webpify_opts: WebpifyCommand) {
     std::fs::create_dir_all(&webpify_opts.output_dir).unwrap();
 
-    // Open path as DynamicImage
-    let entries = WalkDir::new(webpify_opts.input_dir)
-        .into_iter()
-        .filter_map(|entry| entry.ok());
-    // Put webp-image in a separate webp-folder in the location of the original image.
-
-    entries.for_each(|file| {
-        if file.file_type().is_file() {
-            if let Some(ext_os_str) = file.path().extension() {
-                let ext = ext_os_str.to_string_lossy();
-
-                if is_jpeg_or_png(&ext) {
-                    let image = ImageReader::open(file.path());
-                    let dyn_image: DynamicImage = match image {
-                        Ok(img) => img.with_guessed_format().unwrap().decode().unwrap(), //ImageReader::with_guessed_format() function guesses if image needs to be opened in JPEG or PNG format.
-                        Err(e) => {
-                            exit_with_error!("Error: {}", e);
-                        }
-                    };
-
-                    // Make webp::Encoder from DynamicImage.
-                    let encoder: Encoder = Encoder::from_image(&dyn_image).unwrap();
-                    // Encode image into WebPMemory.
-                    let encoded_webp: WebPMemory = encoder.encode(65f32);
-                    // Get filename of original image.
-                    let filename_original_image =
-                        file.path().file_stem().unwrap().to_str().unwrap();
-                    // Make full output path for webp-image.
-                    let webp_image_path = format!(
-                        "{}/{}.webp",
-                        webpify_opts.output_dir.to_string_lossy(),
-                        filename_original_image
-                    );
-
-                    // Make File-stream for WebP-result and write bytes into it, and save to path "output.webp".
-                    let mut webp_image = File::create(webp_image_path).unwrap();
-
-                    webp_image.write_all(encoded_webp.as_bytes()).unwrap();
-                }
-            }
-        }
+    let files = image_files::collect(
+        &webpify_opts.input_dir,
+        webpify_opts.recursive,
+        &["png", "jpeg", "jpg"],
+    );
+    let results = image_files::process_parallel(&files, webpify_opts.jobs, |file_path| {
+        webpify_file(&webpify_opts.input_dir, &webpify_opts.output_dir, file_path)
     });
+    let mut failed = 0;
+
+    // Report failures in the order the files were found, not the order they finished.
+    for (file_path, result) in files.iter().zip(results) {
+        if let Err(e) = result {
+            eprintln!("{}: {}", file_path.display(), e);
+            failed += 1;
+        }
+    }
+    image_files::exit_if_failed(failed, files.len());
 }